@@ -0,0 +1,5 @@
+-- Track refresh token families so rotated tokens can be detected when replayed.
+ALTER TABLE user_sessions
+    ADD COLUMN family_id VARCHAR(36) NOT NULL AFTER jti,
+    ADD COLUMN revoked_at TIMESTAMP NULL DEFAULT NULL AFTER last_seen_at,
+    ADD INDEX idx_user_sessions_family_id (family_id);
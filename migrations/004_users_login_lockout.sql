@@ -0,0 +1,4 @@
+-- Count consecutive failed logins and lock the account once the limit is reached.
+ALTER TABLE users
+    ADD COLUMN failed_login_attempts INT NOT NULL DEFAULT 0 AFTER email_verified_at,
+    ADD COLUMN locked_until TIMESTAMP NULL DEFAULT NULL AFTER failed_login_attempts;
@@ -0,0 +1,2 @@
+-- Argon2id hashes are longer than bcrypt ones.
+ALTER TABLE users MODIFY COLUMN password VARCHAR(255) NOT NULL;
@@ -0,0 +1,20 @@
+-- TOTP two-factor authentication. totp_secret is set on enrollment and only
+-- takes effect once totp_enabled_at is set by confirming a code.
+ALTER TABLE users
+    ADD COLUMN totp_secret VARCHAR(64) NULL DEFAULT NULL AFTER locked_until,
+    ADD COLUMN totp_enabled_at TIMESTAMP NULL DEFAULT NULL AFTER totp_secret,
+    ADD COLUMN totp_last_used_step BIGINT NULL DEFAULT NULL AFTER totp_enabled_at;
+
+-- Single-use recovery codes for when the authenticator is lost. Only a SHA-256
+-- hash of each code is stored.
+CREATE TABLE mfa_recovery_codes (
+    id BIGINT NOT NULL AUTO_INCREMENT,
+    user_id INT NOT NULL,
+    code_hash CHAR(64) NOT NULL,
+    used_at TIMESTAMP NULL DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    KEY idx_mfa_recovery_codes_user_id (user_id),
+    CONSTRAINT fk_mfa_recovery_codes_user_id FOREIGN KEY (user_id) REFERENCES users (id)
+        ON DELETE CASCADE ON UPDATE RESTRICT
+);
@@ -0,0 +1,4 @@
+-- Accounts suspended by an administrator cannot log in until unsuspended.
+ALTER TABLE users
+    ADD COLUMN suspended_at TIMESTAMP NULL DEFAULT NULL AFTER totp_last_used_step,
+    ADD COLUMN suspended_reason VARCHAR(255) NULL DEFAULT NULL AFTER suspended_at;
@@ -1,19 +1,55 @@
 use dotenvy::dotenv;
-use std::env;
 mod modules;
+use modules::config::AppConfig;
 use modules::database::connection::connect_to_mysql_db;
+use modules::logging::init_logging;
+use modules::metrics::instrument_db;
 use modules::routes::server::run_server;
+use modules::shutdown::finish;
+use ntex::time::Seconds;
 
 #[ntex::main]
 async fn main() -> std::io::Result<()> {
     // Load environment variables from .env file
-    dotenv().ok();
+    let dotenv_result = dotenv();
 
-    let db = connect_to_mysql_db().await;
+    // Structured JSON logs, levels from RUST_LOG
+    init_logging();
 
-    // Load port from environment variable or default to 9001
-    let app_port = env::var("APP_PORT").unwrap_or_else(|_| "9001".to_string());
-    let app_port = app_port.parse::<u16>().unwrap_or(9001);
+    // A missing .env is fine, but a line that does not parse stops dotenvy from
+    // reading the rest of the file, which would silently drop those variables
+    match dotenv_result {
+        Err(dotenvy::Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to load .env");
+            std::process::exit(1);
+        }
+        Ok(_) => {}
+    }
 
-    run_server(app_port, db).await
+    // Read and validate the configuration before anything else
+    let config = match AppConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut db = connect_to_mysql_db(&config.db_url).await;
+
+    // Time every database query for /metrics
+    instrument_db(&mut db);
+
+    // The mail transport selected by MAIL_TRANSPORT was built with the config
+    let mailer = config.mailer.clone();
+
+    let grace_period = Seconds(config.shutdown_grace_period_seconds);
+
+    run_server(config, db.clone(), mailer).await?;
+
+    // The server stopped: send what is still queued and close the pool
+    finish(db, grace_period).await;
+
+    Ok(())
 }
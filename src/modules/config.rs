@@ -0,0 +1,807 @@
+use crate::modules::mail::file::FileMailer;
+use crate::modules::mail::log::LogMailer;
+use crate::modules::mail::noop::NoopMailer;
+use crate::modules::mail::smtp::{SmtpMailer, SmtpSettings, SmtpTls};
+use crate::modules::mail::{MailTransport, SharedMailer};
+use crate::modules::middleware::rate_limit::RateLimitKey;
+use crate::modules::utils::jwt_keys::{JwtKeys, parse_algorithm, parse_public_key_files};
+use crate::modules::utils::lockout::LockoutPolicy;
+use crate::modules::utils::password_policy::{self, PasswordPolicy, load_blocklist};
+use crate::modules::utils::security::{PasswordHasher, hash_token};
+use argon2::Params;
+use chrono::Duration;
+use jsonwebtoken::Algorithm;
+use lettre::message::Mailbox;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Longest account lockout that can be configured, a year.
+const MAX_LOCKOUT_MINUTES: i64 = 365 * 24 * 60;
+
+/// Highest number of requests per minute a rate limit can allow.
+const MAX_RATE_LIMIT: u32 = 1_000_000;
+
+/// Shortest `METRICS_TOKEN` accepted.
+const MIN_METRICS_TOKEN_LENGTH: usize = 32;
+
+/// How access tokens are checked, read from `SESSION_MODE`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SessionMode {
+    /// Access tokens are trusted until they expire (`jwt_stateless`).
+    Stateless,
+    /// Access tokens are only accepted while their session still exists in
+    /// `user_sessions` (`jwt_server_stateful`).
+    ServerStateful,
+}
+
+impl FromStr for SessionMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "jwt_stateless" => Ok(SessionMode::Stateless),
+            "jwt_server_stateful" => Ok(SessionMode::ServerStateful),
+            _ => Err("must be jwt_stateless or jwt_server_stateful".to_string()),
+        }
+    }
+}
+
+/// What registering with the email of a soft-deleted account does, read from
+/// `DELETED_EMAIL_REGISTRATION`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DeletedEmailRegistration {
+    /// Refuse it like any other taken email address (`reject`).
+    Reject,
+    /// Create a new, unrelated account; the deleted one is kept as is (`allow`).
+    Allow,
+}
+
+impl FromStr for DeletedEmailRegistration {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "reject" => Ok(DeletedEmailRegistration::Reject),
+            "allow" => Ok(DeletedEmailRegistration::Allow),
+            _ => Err("must be reject or allow".to_string()),
+        }
+    }
+}
+
+/// Requests per minute allowed by each rate limit policy of the /v1 scope.
+#[derive(Clone)]
+pub struct RateLimits {
+    /// `POST /v1/login` (`RATE_LIMIT_LOGIN`).
+    pub login: u32,
+    /// `POST /v1/users` (`RATE_LIMIT_REGISTER`).
+    pub register: u32,
+    /// `POST /v1/password/*` (`RATE_LIMIT_PASSWORD`).
+    pub password: u32,
+    /// `/v1/me/*`, counted per user (`RATE_LIMIT_USER`).
+    pub user: u32,
+    /// Every other /v1 route (`RATE_LIMIT_DEFAULT`).
+    pub default: u32,
+    /// What the default policy counts requests by (`RATE_LIMIT_DEFAULT_KEY`).
+    pub default_key: RateLimitKey,
+    /// SHA-256 hashes of the API keys that are counted per key rather than per
+    /// IP (`RATE_LIMIT_API_KEYS`).
+    pub api_keys: Arc<HashSet<String>>,
+}
+
+/// Application settings, loaded from the environment once at startup and shared
+/// with handlers through `State<AppConfig>`.
+#[derive(Clone)]
+pub struct AppConfig {
+    pub app_port: u16,
+    pub app_version: String,
+    pub db_url: String,
+    /// Keys tokens are signed and verified with, loaded from disk at startup.
+    pub jwt_keys: Arc<JwtKeys>,
+    pub session_mode: SessionMode,
+    /// `ACCESS_TOKEN_EXPIRE_MINUTES`.
+    pub access_token_lifetime: Duration,
+    /// `REFRESH_TOKEN_EXPIRE_DAYS`, also the max age of the refresh token cookie.
+    pub refresh_token_lifetime: Duration,
+    /// `EMAIL_VERIFICATION_EXPIRE_HOURS`.
+    pub email_verification_lifetime: Duration,
+    pub email_verification_url: String,
+    pub require_email_verification: bool,
+    pub password_reset_url: String,
+    pub password_reset_expire_minutes: i64,
+    /// Rules for new passwords, with the blocklist loaded from disk at startup.
+    pub password_policy: Arc<PasswordPolicy>,
+    /// Argon2id parameters and the bound on pending hashing jobs.
+    pub password_hasher: PasswordHasher,
+    pub lockout: LockoutPolicy,
+    pub rate_limits: RateLimits,
+    /// Outbound mail transport selected by `MAIL_TRANSPORT`, built at startup.
+    pub mailer: SharedMailer,
+    /// Issuer shown in authenticator apps next to the account.
+    pub mfa_issuer: String,
+    /// `MFA_CHALLENGE_EXPIRE_MINUTES`.
+    pub mfa_challenge_lifetime: Duration,
+    pub deleted_email_registration: DeletedEmailRegistration,
+    /// Number of reverse proxies in front of the app that append to
+    /// `X-Forwarded-For` (`TRUSTED_PROXY_COUNT`), or zero when the header is not
+    /// trusted (`TRUST_PROXY_HEADERS`).
+    pub trusted_proxies: usize,
+    /// SHA-256 hash of `METRICS_TOKEN`, the bearer token `/metrics` requires.
+    /// The endpoint is disabled when it is not set.
+    pub metrics_token_hash: Option<String>,
+    /// How long `/readyz` waits for each dependency before calling it down.
+    pub readiness_timeout_ms: i64,
+    /// How long `/readyz` fails before the listener is closed on shutdown, so load
+    /// balancers stop routing new requests here first.
+    pub shutdown_drain_delay_seconds: u16,
+    /// How long in-flight requests, and then queued emails, get to finish on shutdown.
+    pub shutdown_grace_period_seconds: u16,
+}
+
+/// The variables as found in the environment, before any parsing.
+#[derive(Deserialize)]
+struct RawConfig {
+    env: Option<String>,
+    app_port: Option<String>,
+    app_version: Option<String>,
+    db_url: Option<String>,
+    jwt_secret: Option<String>,
+    jwt_algorithm: Option<String>,
+    jwt_key_id: Option<String>,
+    jwt_private_key_file: Option<String>,
+    jwt_public_key_files: Option<String>,
+    session_mode: Option<String>,
+    access_token_expire_minutes: Option<String>,
+    refresh_token_expire_days: Option<String>,
+    email_verification_expire_hours: Option<String>,
+    email_verification_url: Option<String>,
+    require_email_verification: Option<String>,
+    password_reset_url: Option<String>,
+    password_reset_expire_minutes: Option<String>,
+    password_min_length: Option<String>,
+    password_min_character_classes: Option<String>,
+    password_blocklist_file: Option<String>,
+    password_hash_max_pending: Option<String>,
+    argon2_memory_kib: Option<String>,
+    argon2_iterations: Option<String>,
+    argon2_parallelism: Option<String>,
+    login_max_failed_attempts: Option<String>,
+    login_lockout_minutes: Option<String>,
+    login_max_lockout_minutes: Option<String>,
+    login_ip_max_failed_attempts: Option<String>,
+    rate_limit_login: Option<String>,
+    rate_limit_register: Option<String>,
+    rate_limit_password: Option<String>,
+    rate_limit_user: Option<String>,
+    rate_limit_default: Option<String>,
+    rate_limit_default_key: Option<String>,
+    rate_limit_api_keys: Option<String>,
+    mail_transport: Option<String>,
+    mail_from: Option<String>,
+    mail_file_dir: Option<String>,
+    smtp_host: Option<String>,
+    smtp_port: Option<String>,
+    smtp_username: Option<String>,
+    smtp_password: Option<String>,
+    smtp_tls: Option<String>,
+    mfa_issuer: Option<String>,
+    mfa_challenge_expire_minutes: Option<String>,
+    deleted_email_registration: Option<String>,
+    trust_proxy_headers: Option<String>,
+    trusted_proxy_count: Option<String>,
+    metrics_token: Option<String>,
+    readiness_timeout_ms: Option<String>,
+    shutdown_drain_delay_seconds: Option<String>,
+    shutdown_grace_period_seconds: Option<String>,
+}
+
+/// Every problem found while loading the configuration.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Collects problems instead of stopping at the first one, so a single run
+/// reports everything that needs fixing.
+#[derive(Default)]
+struct Checker {
+    problems: Vec<String>,
+}
+
+impl Checker {
+    /// A variable that must be set to a non-empty value.
+    fn required(&mut self, name: &str, value: Option<String>) -> String {
+        match value.filter(|v| !v.trim().is_empty()) {
+            Some(value) => value,
+            None => {
+                self.problems.push(format!("{} is missing", name));
+                String::new()
+            }
+        }
+    }
+
+    /// An optional variable parsed into `T`, falling back to `default` when unset.
+    fn parse<T>(&mut self, name: &str, value: Option<String>, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match value.filter(|v| !v.trim().is_empty()) {
+            None => default,
+            Some(value) => match value.trim().parse::<T>() {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    self.problems
+                        .push(format!("{} is invalid ({:?}): {}", name, value, e));
+                    default
+                }
+            },
+        }
+    }
+
+    /// Load the JWT keys for the configured algorithm. Nothing is returned when
+    /// a problem was found, the configuration is rejected anyway.
+    fn jwt_keys(&mut self, raw: &RawConfig) -> Option<JwtKeys> {
+        let algorithm = match raw.jwt_algorithm.as_deref().map(str::trim) {
+            None | Some("") => Algorithm::HS256,
+            Some(value) => match parse_algorithm(value) {
+                Ok(algorithm) => algorithm,
+                Err(e) => {
+                    self.problems
+                        .push(format!("JWT_ALGORITHM is invalid ({:?}): {}", value, e));
+                    return None;
+                }
+            },
+        };
+
+        let kid = raw.jwt_key_id.clone().filter(|v| !v.trim().is_empty());
+
+        if algorithm == Algorithm::HS256 {
+            let secret = self.required("JWT_SECRET", raw.jwt_secret.clone());
+            return Some(JwtKeys::hmac(&secret, kid));
+        }
+
+        let kid = self.required("JWT_KEY_ID", kid);
+        let private_key_file =
+            self.required("JWT_PRIVATE_KEY_FILE", raw.jwt_private_key_file.clone());
+        let public_key_files =
+            self.required("JWT_PUBLIC_KEY_FILES", raw.jwt_public_key_files.clone());
+        let public_key_files = match parse_public_key_files(&public_key_files) {
+            Ok(files) => files,
+            Err(e) => {
+                self.problems
+                    .push(format!("JWT_PUBLIC_KEY_FILES is invalid: {}", e));
+                return None;
+            }
+        };
+
+        if !self.problems.is_empty() {
+            return None;
+        }
+
+        match JwtKeys::asymmetric(algorithm, &kid, &private_key_file, &public_key_files) {
+            Ok(keys) => Some(keys),
+            Err(problems) => {
+                self.problems.extend(problems);
+                None
+            }
+        }
+    }
+
+    /// Build the password policy and load its blocklist. A blocklist that cannot
+    /// be read is a problem, the check must not be skipped silently.
+    fn password_policy(&mut self, raw: &RawConfig) -> PasswordPolicy {
+        let min_length = self.range(
+            "PASSWORD_MIN_LENGTH",
+            raw.password_min_length.clone(),
+            8,
+            1..=password_policy::MAX_LENGTH,
+        );
+        let min_character_classes = self.range(
+            "PASSWORD_MIN_CHARACTER_CLASSES",
+            raw.password_min_character_classes.clone(),
+            1,
+            1..=4,
+        );
+
+        let blocklist_file = raw
+            .password_blocklist_file
+            .clone()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| "data/common-passwords.txt".to_string());
+        let blocklist = load_blocklist(&blocklist_file).unwrap_or_else(|e| {
+            self.problems
+                .push(format!("PASSWORD_BLOCKLIST_FILE is invalid: {}", e));
+            Default::default()
+        });
+
+        PasswordPolicy::new(min_length, min_character_classes, blocklist)
+    }
+
+    /// Argon2id parameters, checked together since they constrain each other.
+    fn password_hasher(&mut self, raw: &RawConfig) -> PasswordHasher {
+        let memory_kib = self.range(
+            "ARGON2_MEMORY_KIB",
+            raw.argon2_memory_kib.clone(),
+            19 * 1024,
+            1..=4 * 1024 * 1024,
+        );
+        let iterations = self.range(
+            "ARGON2_ITERATIONS",
+            raw.argon2_iterations.clone(),
+            2,
+            1..=100,
+        );
+        let parallelism = self.range(
+            "ARGON2_PARALLELISM",
+            raw.argon2_parallelism.clone(),
+            1,
+            1..=64,
+        );
+        let params = Params::new(memory_kib, iterations, parallelism, None).unwrap_or_else(|e| {
+            self.problems.push(format!(
+                "ARGON2_MEMORY_KIB, ARGON2_ITERATIONS and ARGON2_PARALLELISM are invalid: {}",
+                e
+            ));
+            Params::default()
+        });
+
+        // Four pending jobs per CPU by default
+        let default_max_pending = std::thread::available_parallelism()
+            .map(|n| n.get() * 4)
+            .unwrap_or(16);
+        let max_pending = self.range(
+            "PASSWORD_HASH_MAX_PENDING",
+            raw.password_hash_max_pending.clone(),
+            default_max_pending,
+            1..=10_000,
+        );
+
+        PasswordHasher::new(params, max_pending)
+    }
+
+    /// Build the mail transport selected by `MAIL_TRANSPORT`. Nothing is returned
+    /// when a problem was found, the configuration is rejected anyway.
+    fn mailer(&mut self, raw: &RawConfig) -> Option<SharedMailer> {
+        let from = raw
+            .mail_from
+            .clone()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| "rubete <no-reply@localhost>".to_string());
+        let from = match from.trim().parse::<Mailbox>() {
+            Ok(from) => Some(from),
+            Err(e) => {
+                self.problems
+                    .push(format!("MAIL_FROM is invalid ({:?}): {}", from, e));
+                None
+            }
+        };
+
+        // Mail is only logged instead of sent by default during development,
+        // anywhere else a missing transport would silently drop every email
+        let development = raw.env.as_deref().map(str::trim) == Some("development");
+        let transport = match raw.mail_transport.clone().filter(|v| !v.trim().is_empty()) {
+            None if development => MailTransport::Log,
+            None => {
+                self.problems.push(
+                    "MAIL_TRANSPORT is missing (only ENV=development defaults to log)".to_string(),
+                );
+                return None;
+            }
+            value => self.parse("MAIL_TRANSPORT", value, MailTransport::Log),
+        };
+
+        let mailer: Result<SharedMailer, String> = match transport {
+            MailTransport::Smtp => {
+                let tls = self.parse("SMTP_TLS", raw.smtp_tls.clone(), SmtpTls::StartTls);
+                let settings = SmtpSettings {
+                    host: self.required("SMTP_HOST", raw.smtp_host.clone()),
+                    port: self.parse("SMTP_PORT", raw.smtp_port.clone(), tls.default_port()),
+                    tls,
+                    credentials: raw
+                        .smtp_username
+                        .clone()
+                        .filter(|v| !v.trim().is_empty())
+                        .map(|username| (username, raw.smtp_password.clone().unwrap_or_default())),
+                };
+
+                // Only build the transport from valid settings
+                let from = from.filter(|_| self.problems.is_empty())?;
+
+                SmtpMailer::new(&settings, from)
+                    .map(|mailer| Arc::new(mailer) as SharedMailer)
+                    .map_err(|e| format!("SMTP_HOST is invalid: {}", e))
+            }
+            MailTransport::File => {
+                let dir = raw
+                    .mail_file_dir
+                    .clone()
+                    .filter(|v| !v.trim().is_empty())
+                    .unwrap_or_else(|| "mail".to_string());
+
+                FileMailer::new(&dir, from?)
+                    .map(|mailer| Arc::new(mailer) as SharedMailer)
+                    .map_err(|e| format!("MAIL_FILE_DIR is invalid: {}", e))
+            }
+            MailTransport::Log => Ok(Arc::new(LogMailer)),
+            MailTransport::Noop => Ok(Arc::new(NoopMailer)),
+        };
+
+        mailer.map_err(|e| self.problems.push(e)).ok()
+    }
+
+    /// Hash of the `/metrics` token, if one is set. Short tokens are refused, the
+    /// endpoint must not be open to guessing.
+    fn metrics_token_hash(&mut self, raw: &RawConfig) -> Option<String> {
+        let token = raw
+            .metrics_token
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())?;
+
+        if token.chars().count() < MIN_METRICS_TOKEN_LENGTH {
+            self.problems.push(format!(
+                "METRICS_TOKEN must be at least {} characters",
+                MIN_METRICS_TOKEN_LENGTH
+            ));
+        }
+
+        Some(hash_token(token))
+    }
+
+    /// Like `parse`, for values that must lie within `range`. Out of range values
+    /// are replaced by `default`, so they cannot cause follow-up failures.
+    fn range<T>(
+        &mut self,
+        name: &str,
+        value: Option<String>,
+        default: T,
+        range: RangeInclusive<T>,
+    ) -> T
+    where
+        T: FromStr + PartialOrd + fmt::Display + Copy,
+        T::Err: fmt::Display,
+    {
+        let parsed = self.parse(name, value, default);
+        if range.contains(&parsed) {
+            return parsed;
+        }
+
+        self.problems.push(format!(
+            "{} must be between {} and {}",
+            name,
+            range.start(),
+            range.end()
+        ));
+        default
+    }
+
+    /// A duration counted in the unit of `to_duration` (`Duration::try_minutes`,
+    /// `Duration::try_days`, ...), within `range` of that unit.
+    fn duration(
+        &mut self,
+        name: &str,
+        value: Option<String>,
+        default: i64,
+        range: RangeInclusive<i64>,
+        to_duration: fn(i64) -> Option<Duration>,
+    ) -> Duration {
+        let amount = self.range(name, value, default, range);
+        to_duration(amount).unwrap_or_else(|| {
+            self.problems.push(format!("{} is too large", name));
+            Duration::zero()
+        })
+    }
+}
+
+impl AppConfig {
+    /// Load and validate the configuration, listing every missing or invalid variable.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(std::env::vars())
+    }
+
+    fn from_vars<I>(vars: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let raw =
+            envy::from_iter::<_, RawConfig>(vars).map_err(|e| ConfigError(vec![e.to_string()]))?;
+        let mut check = Checker::default();
+        let jwt_keys = check.jwt_keys(&raw);
+        let password_policy = check.password_policy(&raw);
+        let password_hasher = check.password_hasher(&raw);
+        let mailer = check.mailer(&raw);
+        let metrics_token_hash = check.metrics_token_hash(&raw);
+        let api_keys = raw
+            .rate_limit_api_keys
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(hash_token)
+            .collect();
+        let trust_proxy_headers =
+            check.parse("TRUST_PROXY_HEADERS", raw.trust_proxy_headers, false);
+        let trusted_proxy_count =
+            check.range("TRUSTED_PROXY_COUNT", raw.trusted_proxy_count, 1, 1..=10);
+
+        let config = AppConfig {
+            app_port: check.parse("APP_PORT", raw.app_port, 9001),
+            app_version: raw.app_version.unwrap_or_else(|| "0.0.1".to_string()),
+            db_url: check.required("DB_URL", raw.db_url),
+            // Without keys a problem has been recorded and the config is rejected below
+            jwt_keys: Arc::new(jwt_keys.unwrap_or_else(|| JwtKeys::hmac("", None))),
+            session_mode: check.parse("SESSION_MODE", raw.session_mode, SessionMode::Stateless),
+            access_token_lifetime: check.duration(
+                "ACCESS_TOKEN_EXPIRE_MINUTES",
+                raw.access_token_expire_minutes,
+                15,
+                1..=24 * 60,
+                Duration::try_minutes,
+            ),
+            refresh_token_lifetime: check.duration(
+                "REFRESH_TOKEN_EXPIRE_DAYS",
+                raw.refresh_token_expire_days,
+                7,
+                1..=365,
+                Duration::try_days,
+            ),
+            email_verification_lifetime: check.duration(
+                "EMAIL_VERIFICATION_EXPIRE_HOURS",
+                raw.email_verification_expire_hours,
+                24,
+                1..=30 * 24,
+                Duration::try_hours,
+            ),
+            email_verification_url: raw
+                .email_verification_url
+                .unwrap_or_else(|| "http://localhost:5173/verify-email".to_string()),
+            require_email_verification: check.parse(
+                "REQUIRE_EMAIL_VERIFICATION",
+                raw.require_email_verification,
+                false,
+            ),
+            password_reset_url: raw
+                .password_reset_url
+                .unwrap_or_else(|| "http://localhost:5173/reset-password".to_string()),
+            password_reset_expire_minutes: check.range(
+                "PASSWORD_RESET_EXPIRE_MINUTES",
+                raw.password_reset_expire_minutes,
+                30,
+                1..=24 * 60,
+            ),
+            password_policy: Arc::new(password_policy),
+            password_hasher,
+            lockout: LockoutPolicy {
+                max_attempts: check.range(
+                    "LOGIN_MAX_FAILED_ATTEMPTS",
+                    raw.login_max_failed_attempts,
+                    5,
+                    1..=1000,
+                ),
+                lockout: check.duration(
+                    "LOGIN_LOCKOUT_MINUTES",
+                    raw.login_lockout_minutes,
+                    15,
+                    1..=MAX_LOCKOUT_MINUTES,
+                    Duration::try_minutes,
+                ),
+                max_lockout: check.duration(
+                    "LOGIN_MAX_LOCKOUT_MINUTES",
+                    raw.login_max_lockout_minutes,
+                    24 * 60,
+                    1..=MAX_LOCKOUT_MINUTES,
+                    Duration::try_minutes,
+                ),
+                ip_max_attempts: check.range(
+                    "LOGIN_IP_MAX_FAILED_ATTEMPTS",
+                    raw.login_ip_max_failed_attempts,
+                    20,
+                    1..=10_000,
+                ),
+            },
+            rate_limits: RateLimits {
+                login: check.range(
+                    "RATE_LIMIT_LOGIN",
+                    raw.rate_limit_login,
+                    10,
+                    1..=MAX_RATE_LIMIT,
+                ),
+                register: check.range(
+                    "RATE_LIMIT_REGISTER",
+                    raw.rate_limit_register,
+                    5,
+                    1..=MAX_RATE_LIMIT,
+                ),
+                password: check.range(
+                    "RATE_LIMIT_PASSWORD",
+                    raw.rate_limit_password,
+                    5,
+                    1..=MAX_RATE_LIMIT,
+                ),
+                user: check.range(
+                    "RATE_LIMIT_USER",
+                    raw.rate_limit_user,
+                    60,
+                    1..=MAX_RATE_LIMIT,
+                ),
+                default: check.range(
+                    "RATE_LIMIT_DEFAULT",
+                    raw.rate_limit_default,
+                    120,
+                    1..=MAX_RATE_LIMIT,
+                ),
+                default_key: check.parse(
+                    "RATE_LIMIT_DEFAULT_KEY",
+                    raw.rate_limit_default_key,
+                    RateLimitKey::Ip,
+                ),
+                api_keys: Arc::new(api_keys),
+            },
+            // Without a mailer a problem has been recorded and the config is rejected below
+            mailer: mailer.unwrap_or_else(|| Arc::new(NoopMailer)),
+            mfa_issuer: raw
+                .mfa_issuer
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| "rubete".to_string()),
+            mfa_challenge_lifetime: check.duration(
+                "MFA_CHALLENGE_EXPIRE_MINUTES",
+                raw.mfa_challenge_expire_minutes,
+                5,
+                1..=60,
+                Duration::try_minutes,
+            ),
+            deleted_email_registration: check.parse(
+                "DELETED_EMAIL_REGISTRATION",
+                raw.deleted_email_registration,
+                DeletedEmailRegistration::Reject,
+            ),
+            trusted_proxies: if trust_proxy_headers {
+                trusted_proxy_count
+            } else {
+                0
+            },
+            metrics_token_hash,
+            readiness_timeout_ms: check.range(
+                "READINESS_TIMEOUT_MS",
+                raw.readiness_timeout_ms,
+                2000,
+                1..=60_000,
+            ),
+            shutdown_drain_delay_seconds: check.parse(
+                "SHUTDOWN_DRAIN_DELAY_SECONDS",
+                raw.shutdown_drain_delay_seconds,
+                5,
+            ),
+            shutdown_grace_period_seconds: check.parse(
+                "SHUTDOWN_GRACE_PERIOD_SECONDS",
+                raw.shutdown_grace_period_seconds,
+                30,
+            ),
+        };
+
+        if config.rate_limits.default_key == RateLimitKey::ApiKey
+            && config.rate_limits.api_keys.is_empty()
+        {
+            check.problems.push(
+                "RATE_LIMIT_DEFAULT_KEY=api_key needs the keys in RATE_LIMIT_API_KEYS".to_string(),
+            );
+        }
+
+        if config.mfa_issuer.contains(':') {
+            check
+                .problems
+                .push("MFA_ISSUER must not contain ':'".to_string());
+        }
+
+        if check.problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(check.problems))
+        }
+    }
+
+    /// Whether access tokens must be checked against `user_sessions`.
+    pub fn is_stateful_session_mode(&self) -> bool {
+        self.session_mode == SessionMode::ServerStateful
+    }
+}
+
+/// Variables of a configuration that loads without problems, for tests.
+#[cfg(test)]
+pub const TEST_VARS: &[(&str, &str)] = &[
+    ("DB_URL", "postgres://localhost/rubete"),
+    ("JWT_SECRET", "a-test-secret-that-is-long-enough-for-hs256"),
+    ("ENV", "development"),
+    ("MAIL_TRANSPORT", "noop"),
+    ("MAIL_FROM", "rubete <no-reply@localhost>"),
+    ("EMAIL_VERIFICATION_URL", "http://localhost/verify"),
+    ("PASSWORD_RESET_URL", "http://localhost/reset"),
+];
+
+#[cfg(test)]
+impl AppConfig {
+    /// Configuration loaded from `TEST_VARS`, otherwise the defaults.
+    pub fn for_tests() -> Self {
+        let vars = TEST_VARS
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        Self::from_vars(vars).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(vars: &[(&str, &str)]) -> Vec<String> {
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        match AppConfig::from_vars(vars) {
+            Ok(_) => Vec::new(),
+            Err(ConfigError(problems)) => problems,
+        }
+    }
+
+    #[test]
+    fn valid_configuration_loads() {
+        assert_eq!(problems(TEST_VARS), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let mut vars = TEST_VARS.to_vec();
+        vars.retain(|(name, _)| *name != "DB_URL");
+        vars.extend([
+            ("APP_PORT", "not-a-port"),
+            ("ACCESS_TOKEN_EXPIRE_MINUTES", "0"),
+            ("REFRESH_TOKEN_EXPIRE_DAYS", "9223372036854775807"),
+            ("LOGIN_LOCKOUT_MINUTES", "9223372036854775807"),
+            ("PASSWORD_MIN_CHARACTER_CLASSES", "5"),
+            ("RATE_LIMIT_LOGIN", "-1"),
+            ("MFA_ISSUER", "rubete:prod"),
+        ]);
+
+        let problems = problems(&vars);
+        for name in [
+            "DB_URL",
+            "APP_PORT",
+            "ACCESS_TOKEN_EXPIRE_MINUTES",
+            "REFRESH_TOKEN_EXPIRE_DAYS",
+            "LOGIN_LOCKOUT_MINUTES",
+            "PASSWORD_MIN_CHARACTER_CLASSES",
+            "RATE_LIMIT_LOGIN",
+            "MFA_ISSUER",
+        ] {
+            assert!(
+                problems.iter().any(|problem| problem.starts_with(name)),
+                "no problem reported for {}: {:?}",
+                name,
+                problems
+            );
+        }
+        assert_eq!(problems.len(), 8, "{:?}", problems);
+    }
+
+    #[test]
+    fn range_falls_back_to_default() {
+        let mut check = Checker::default();
+        assert_eq!(check.range("A", Some("7".to_string()), 3, 1..=10), 7);
+        assert_eq!(check.range("B", None, 3, 1..=10), 3);
+        assert_eq!(check.range("C", Some("11".to_string()), 3, 1..=10), 3);
+        assert_eq!(check.problems, ["C must be between 1 and 10"]);
+    }
+}
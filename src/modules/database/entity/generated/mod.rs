@@ -3,6 +3,12 @@
 pub mod prelude;
 
 pub mod activities;
+pub mod mfa_recovery_codes;
+pub mod password_reset_tokens;
+pub mod permissions;
+pub mod role_permissions;
+pub mod roles;
 pub mod user_details;
+pub mod user_roles;
 pub mod user_sessions;
 pub mod users;
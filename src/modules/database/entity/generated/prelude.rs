@@ -1,6 +1,12 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.17
 
 pub use super::activities::Entity as Activities;
+pub use super::mfa_recovery_codes::Entity as MfaRecoveryCodes;
+pub use super::password_reset_tokens::Entity as PasswordResetTokens;
+pub use super::permissions::Entity as Permissions;
+pub use super::role_permissions::Entity as RolePermissions;
+pub use super::roles::Entity as Roles;
 pub use super::user_details::Entity as UserDetails;
+pub use super::user_roles::Entity as UserRoles;
 pub use super::user_sessions::Entity as UserSessions;
 pub use super::users::Entity as Users;
@@ -11,9 +11,11 @@ pub struct Model {
     pub user_id: i32,
     #[sea_orm(unique)]
     pub jti: String,
+    pub family_id: String,
     pub created_at: Option<DateTimeUtc>,
     pub expires_at: DateTimeUtc,
     pub last_seen_at: Option<DateTimeUtc>,
+    pub revoked_at: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -10,6 +10,14 @@ pub struct Model {
     pub id: i32,
     pub email: String,
     pub password: String,
+    pub email_verified_at: Option<DateTimeUtc>,
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<DateTimeUtc>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTimeUtc>,
+    pub totp_last_used_step: Option<i64>,
+    pub suspended_at: Option<DateTimeUtc>,
+    pub suspended_reason: Option<String>,
     pub created_at: DateTimeUtc,
     pub updated_at: Option<DateTimeUtc>,
     pub deleted_at: Option<DateTimeUtc>,
@@ -19,8 +27,14 @@ pub struct Model {
 pub enum Relation {
     #[sea_orm(has_many = "super::activities::Entity")]
     Activities,
+    #[sea_orm(has_many = "super::mfa_recovery_codes::Entity")]
+    MfaRecoveryCodes,
+    #[sea_orm(has_many = "super::password_reset_tokens::Entity")]
+    PasswordResetTokens,
     #[sea_orm(has_many = "super::user_details::Entity")]
     UserDetails,
+    #[sea_orm(has_many = "super::user_roles::Entity")]
+    UserRoles,
     #[sea_orm(has_many = "super::user_sessions::Entity")]
     UserSessions,
 }
@@ -31,16 +45,43 @@ impl Related<super::activities::Entity> for Entity {
     }
 }
 
+impl Related<super::mfa_recovery_codes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MfaRecoveryCodes.def()
+    }
+}
+
+impl Related<super::password_reset_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswordResetTokens.def()
+    }
+}
+
 impl Related<super::user_details::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserDetails.def()
     }
 }
 
+impl Related<super::user_roles::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserRoles.def()
+    }
+}
+
 impl Related<super::user_sessions::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserSessions.def()
     }
 }
 
+impl Related<super::roles::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::user_roles::Relation::Roles.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::user_roles::Relation::Users.def().rev())
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -3,3 +3,6 @@
 // Include all generated entities
 pub mod generated;
 pub use generated::*;
+
+// Hand-written query scopes
+pub mod scopes;
@@ -0,0 +1,18 @@
+//! Query scopes for the generated entities. Kept out of `generated/` so they
+//! survive regenerating the entities.
+
+use super::users;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, Select};
+
+impl users::Entity {
+    /// Users that have not been soft-deleted. Use it instead of `find` unless
+    /// deleted accounts are wanted, as in the admin endpoints.
+    pub fn find_not_deleted() -> Select<Self> {
+        Self::find().filter(users::Column::DeletedAt.is_null())
+    }
+
+    /// The user with `id`, unless it has been soft-deleted.
+    pub fn find_not_deleted_by_id(id: i32) -> Select<Self> {
+        Self::find_by_id(id).filter(users::Column::DeletedAt.is_null())
+    }
+}
@@ -1,3 +1,5 @@
 pub mod health_check;
 pub mod home;
+pub mod jwks;
+pub mod metrics;
 pub mod module;
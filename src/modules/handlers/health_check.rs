@@ -1,10 +1,120 @@
-use crate::modules::utils::response::send_success;
+use crate::modules::config::AppConfig;
+use crate::modules::mail::SharedMailer;
+use crate::modules::shutdown::is_shutting_down;
+use crate::modules::utils::response::{send_error, send_success};
+use ntex::time::{Millis, timeout};
+use ntex::util::join;
 use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::types::State;
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
 use serde_json::json;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Instant;
 
+/// Result of checking one dependency.
+#[derive(Serialize)]
+struct ComponentStatus {
+    status: &'static str,
+    /// Whether the service is unusable without it.
+    critical: bool,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+/// Run one dependency check under `timeout_ms`. Failures are logged with their
+/// cause; the status document only says whether the check failed or timed out.
+async fn check<F, E>(name: &str, critical: bool, timeout_ms: i64, future: F) -> ComponentStatus
+where
+    F: Future<Output = Result<(), E>>,
+    E: Display,
+{
+    let started = Instant::now();
+    let result = timeout(Millis(timeout_ms as u32), future).await;
+
+    let error = match result {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            tracing::warn!(component = name, error = %e, "Readiness check failed");
+            Some("unavailable")
+        }
+        Err(()) => {
+            tracing::warn!(component = name, timeout_ms, "Readiness check timed out");
+            Some("timeout")
+        }
+    };
+
+    ComponentStatus {
+        status: if error.is_none() { "up" } else { "down" },
+        critical,
+        latency_ms: started.elapsed().as_millis(),
+        error,
+    }
+}
+
+/// Liveness probe: the process is up and serving requests. Dependencies are
+/// not checked, so a database outage does not get the process restarted.
+#[web::get("/livez")]
+pub async fn livez() -> impl web::Responder {
+    send_success("API is alive", json!({ "status": "alive" }))
+}
+
+/// Kept for existing monitors; same as `/livez`.
 #[web::get("/healthz")]
 pub async fn health_check() -> impl web::Responder {
-    let data = json!({ "status": "healthy" });
+    send_success("API is healthy", json!({ "status": "healthy" }))
+}
+
+/// Readiness probe: checks the database and the mailer. Answers 503 when a
+/// critical dependency is down or the server is shutting down, so the instance
+/// is taken out of rotation, and `degraded` when only an optional one is down.
+#[web::get("/readyz")]
+pub async fn readyz(
+    db: State<DatabaseConnection>,
+    mailer: State<SharedMailer>,
+    config: State<AppConfig>,
+) -> HttpResponse {
+    if is_shutting_down() {
+        return send_error(
+            503,
+            "shutting_down",
+            "Server is shutting down",
+            Some(json!({ "status": "shutting_down" })),
+        );
+    }
+
+    let timeout_ms = config.readiness_timeout_ms;
+
+    // Mail is sent in the background, a mail outage does not fail requests
+    let (database, mail) = join(
+        check("database", true, timeout_ms, db.ping()),
+        check("mailer", false, timeout_ms, mailer.check()),
+    )
+    .await;
+
+    let critical_down = [&database, &mail]
+        .iter()
+        .any(|component| component.critical && component.error.is_some());
+    let optional_down = [&database, &mail]
+        .iter()
+        .any(|component| !component.critical && component.error.is_some());
+    let components = json!({ "database": database, "mailer": mail });
+
+    if critical_down {
+        return send_error(
+            503,
+            "not_ready",
+            "A critical dependency is unavailable",
+            Some(json!({ "status": "not_ready", "components": components })),
+        );
+    }
 
-    send_success("API is healthy", data)
+    let status = if optional_down { "degraded" } else { "ready" };
+    send_success(
+        "API is ready",
+        json!({ "status": status, "components": components }),
+    )
 }
@@ -0,0 +1,14 @@
+use crate::modules::config::AppConfig;
+use ntex::http::header;
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::types::State;
+
+/// Public keys tokens can be verified with. Served as a bare JWK Set rather than
+/// wrapped in `SuccessResponse`, so standard JWT libraries can consume it.
+#[web::get("/.well-known/jwks.json")]
+pub async fn jwks(config: State<AppConfig>) -> impl web::Responder {
+    HttpResponse::Ok()
+        .header(header::CACHE_CONTROL, "public, max-age=300")
+        .json(config.jwt_keys.jwks())
+}
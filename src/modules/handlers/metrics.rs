@@ -0,0 +1,49 @@
+use crate::modules::config::AppConfig;
+use crate::modules::metrics::metrics;
+use crate::modules::utils::auth::bearer_token;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::security::hash_token;
+use ntex::http::header;
+use ntex::web;
+use ntex::web::types::State;
+use ntex::web::{HttpRequest, HttpResponse};
+use sea_orm::DatabaseConnection;
+
+/// Metrics in the Prometheus text format. Served bare rather than wrapped in
+/// `SuccessResponse`, so Prometheus can scrape it.
+///
+/// Requires `Authorization: Bearer <METRICS_TOKEN>`; without a configured token
+/// the endpoint does not exist. Scrapes never query the database, the session
+/// gauge is refreshed in the background by `refresh_session_gauge`.
+#[web::get("/metrics")]
+pub async fn prometheus_metrics(
+    req: HttpRequest,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+) -> Result<HttpResponse, AppError> {
+    let expected = config
+        .metrics_token_hash
+        .as_deref()
+        .ok_or_else(|| AppError::not_found("not_found", "Not found"))?;
+
+    if bearer_token(req.headers())
+        .map(|token| hash_token(&token))
+        .as_deref()
+        != Some(expected)
+    {
+        return Err(AppError::unauthorized(
+            "invalid_token",
+            "A valid metrics token is required",
+        ));
+    }
+
+    metrics().observe_pool(db.get_ref());
+
+    let body = metrics()
+        .render()
+        .map_err(|e| AppError::internal("metrics_error", "Failed to render metrics", e))?;
+
+    Ok(HttpResponse::Ok()
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(body))
+}
@@ -1 +1,5 @@
+pub mod admin;
+pub mod email;
+pub mod me;
+pub mod password;
 pub mod users;
@@ -0,0 +1,36 @@
+use crate::modules::database::entity::permissions::Entity as PermissionsEntity;
+use crate::modules::database::entity::roles::{self, Entity as RolesEntity};
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::response::send_success;
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::types::State;
+use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder};
+use serde_json::json;
+
+/// List every role with the permissions it grants.
+#[web::get("")]
+pub async fn list_roles(db: State<DatabaseConnection>) -> Result<HttpResponse, AppError> {
+    let roles = RolesEntity::find()
+        .find_with_related(PermissionsEntity)
+        .order_by_asc(roles::Column::Name)
+        .all(db.get_ref())
+        .await?;
+
+    let roles: Vec<_> = roles
+        .into_iter()
+        .map(|(role, permissions)| {
+            json!({
+                "id": role.id,
+                "name": role.name,
+                "description": role.description,
+                "permissions": permissions.into_iter().map(|p| p.name).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+
+    Ok(send_success(
+        "Roles fetched successfully",
+        json!({ "roles": roles }),
+    ))
+}
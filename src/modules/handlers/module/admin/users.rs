@@ -0,0 +1,3 @@
+pub mod actions;
+pub mod list;
+pub mod show;
@@ -0,0 +1,265 @@
+use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
+use crate::modules::database::entity::user_sessions::{self, Entity as UserSessionsEntity};
+use crate::modules::database::entity::users::{
+    self, ActiveModel as UserActiveModel, Entity as UsersEntity,
+};
+use crate::modules::handlers::module::admin::users::list::UserStatus;
+use crate::modules::utils::auth::AuthUser;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::response::send_success;
+use chrono::Utc;
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::error::JsonPayloadError;
+use ntex::web::types::{Json, Path, State};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct SuspendUserRequest {
+    #[validate(length(max = 255, message = "reason must be at most 255 characters"))]
+    pub reason: Option<String>,
+}
+
+/// Change an administrator makes to a user account.
+enum AdminAction {
+    Suspend { reason: Option<String> },
+    Unsuspend,
+    Delete,
+    Restore,
+    Logout,
+}
+
+impl AdminAction {
+    fn activity_type(&self) -> &'static str {
+        match self {
+            AdminAction::Suspend { .. } => "admin_suspend_user",
+            AdminAction::Unsuspend => "admin_unsuspend_user",
+            AdminAction::Delete => "admin_delete_user",
+            AdminAction::Restore => "admin_restore_user",
+            AdminAction::Logout => "admin_logout_user",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            AdminAction::Suspend { .. } => "User suspended by an administrator",
+            AdminAction::Unsuspend => "User unsuspended by an administrator",
+            AdminAction::Delete => "User deleted by an administrator",
+            AdminAction::Restore => "User restored by an administrator",
+            AdminAction::Logout => "User logged out by an administrator",
+        }
+    }
+
+    fn success_message(&self) -> &'static str {
+        match self {
+            AdminAction::Suspend { .. } => "User suspended",
+            AdminAction::Unsuspend => "User unsuspended",
+            AdminAction::Delete => "User deleted",
+            AdminAction::Restore => "User restored",
+            AdminAction::Logout => "User logged out from all sessions",
+        }
+    }
+
+    /// Whether the action ends every session of the user.
+    fn revokes_sessions(&self) -> bool {
+        matches!(
+            self,
+            AdminAction::Suspend { .. } | AdminAction::Delete | AdminAction::Logout
+        )
+    }
+
+    /// Refuse the action when it does not apply to the user's current state.
+    fn check_state(&self, user: &users::Model) -> Result<(), AppError> {
+        let status = UserStatus::of(user);
+        let (code, message) = match self {
+            AdminAction::Suspend { .. } if status == UserStatus::Suspended => {
+                ("user_already_suspended", "User is already suspended")
+            }
+            AdminAction::Unsuspend if user.suspended_at.is_none() => {
+                ("user_not_suspended", "User is not suspended")
+            }
+            AdminAction::Suspend { .. } | AdminAction::Unsuspend
+                if status == UserStatus::Deleted =>
+            {
+                ("user_deleted", "User has been deleted")
+            }
+            AdminAction::Delete if status == UserStatus::Deleted => {
+                ("user_already_deleted", "User has already been deleted")
+            }
+            AdminAction::Restore if status != UserStatus::Deleted => {
+                ("user_not_deleted", "User has not been deleted")
+            }
+            _ => return Ok(()),
+        };
+
+        Err(AppError::conflict(code, message))
+    }
+}
+
+/// Apply an action to a user in one transaction: update the account, end its
+/// sessions if needed and record the administrator as the actor in `activities`.
+async fn run_action(
+    db: &DatabaseConnection,
+    admin: &AuthUser,
+    user_id: i32,
+    action: AdminAction,
+) -> Result<HttpResponse, AppError> {
+    // Locking oneself out would leave nobody to undo it
+    if user_id == admin.user_id
+        && matches!(action, AdminAction::Suspend { .. } | AdminAction::Delete)
+    {
+        return Err(AppError::bad_request(
+            "cannot_modify_self",
+            "Administrators cannot suspend or delete their own account",
+        ));
+    }
+
+    let user = UsersEntity::find_by_id(user_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found("user_not_found", "User not found"))?;
+
+    action.check_state(&user)?;
+
+    // With DELETED_EMAIL_REGISTRATION=allow the address may have been registered again
+    if let AdminAction::Restore = action {
+        let taken = UsersEntity::find_not_deleted()
+            .filter(users::Column::Email.eq(user.email.clone()))
+            .one(db)
+            .await?;
+
+        if taken.is_some() {
+            return Err(AppError::conflict(
+                "email_in_use",
+                "Another account now uses this email address",
+            ));
+        }
+    }
+
+    let now = Utc::now();
+
+    // Start transaction
+    let txn = db.begin().await?;
+
+    let mut active_user: UserActiveModel = user.into();
+    match &action {
+        AdminAction::Suspend { reason } => {
+            active_user.suspended_at = Set(Some(now));
+            active_user.suspended_reason = Set(reason.clone());
+        }
+        AdminAction::Unsuspend => {
+            active_user.suspended_at = Set(None);
+            active_user.suspended_reason = Set(None);
+        }
+        AdminAction::Delete => active_user.deleted_at = Set(Some(now)),
+        AdminAction::Restore => active_user.deleted_at = Set(None),
+        AdminAction::Logout => {}
+    }
+
+    if active_user.is_changed() {
+        active_user.updated_at = Set(Some(now));
+        active_user.update(&txn).await?;
+    }
+
+    let revoked_sessions = if action.revokes_sessions() {
+        UserSessionsEntity::delete_many()
+            .filter(user_sessions::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?
+            .rows_affected
+    } else {
+        0
+    };
+
+    let mut metadata = json!({ "admin_id": admin.user_id, "revoked_sessions": revoked_sessions });
+    if let AdminAction::Suspend {
+        reason: Some(reason),
+    } = &action
+    {
+        metadata["reason"] = json!(reason);
+    }
+
+    let activity = ActivitiesActiveModel {
+        user_id: Set(admin.user_id),
+        data_id: Set(user_id),
+        data_type: Set("user".to_string()),
+        activity_type: Set(Some(action.activity_type().to_string())),
+        activity_description: Set(Some(action.description().to_string())),
+        metadata: Set(Some(metadata)),
+        ..Default::default()
+    };
+
+    activity.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(send_success(
+        action.success_message(),
+        json!({ "id": user_id, "revoked_sessions": revoked_sessions }),
+    ))
+}
+
+/// Suspend a user: they are logged out and cannot log in until unsuspended.
+#[web::post("/suspend")]
+pub async fn suspend_user(
+    auth: AuthUser,
+    id: Path<i32>,
+    payload: Result<Json<SuspendUserRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    let reason = data.reason.filter(|reason| !reason.trim().is_empty());
+    run_action(
+        db.get_ref(),
+        &auth,
+        id.into_inner(),
+        AdminAction::Suspend { reason },
+    )
+    .await
+}
+
+#[web::post("/unsuspend")]
+pub async fn unsuspend_user(
+    auth: AuthUser,
+    id: Path<i32>,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    run_action(db.get_ref(), &auth, id.into_inner(), AdminAction::Unsuspend).await
+}
+
+/// Soft-delete a user by setting `deleted_at`. They are logged out and can be restored.
+#[web::delete("")]
+pub async fn delete_user(
+    auth: AuthUser,
+    id: Path<i32>,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    run_action(db.get_ref(), &auth, id.into_inner(), AdminAction::Delete).await
+}
+
+#[web::post("/restore")]
+pub async fn restore_user(
+    auth: AuthUser,
+    id: Path<i32>,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    run_action(db.get_ref(), &auth, id.into_inner(), AdminAction::Restore).await
+}
+
+/// End every session of a user on every device.
+#[web::post("/logout")]
+pub async fn force_logout_user(
+    auth: AuthUser,
+    id: Path<i32>,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    run_action(db.get_ref(), &auth, id.into_inner(), AdminAction::Logout).await
+}
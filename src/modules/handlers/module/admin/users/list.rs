@@ -0,0 +1,138 @@
+use crate::modules::database::entity::user_details::{self, Entity as UserDetailsEntity};
+use crate::modules::database::entity::users::{self, Entity as UsersEntity};
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::response::send_success;
+use chrono::{DateTime, Utc};
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::error::QueryPayloadError;
+use ntex::web::types::{Query, State};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use validator::Validate;
+
+/// Account state an administrator can filter users by.
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserStatus {
+    Active,
+    Suspended,
+    Deleted,
+}
+
+impl UserStatus {
+    pub fn of(user: &users::Model) -> Self {
+        if user.deleted_at.is_some() {
+            UserStatus::Deleted
+        } else if user.suspended_at.is_some() {
+            UserStatus::Suspended
+        } else {
+            UserStatus::Active
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct ListUsersQuery {
+    #[validate(range(min = 1, message = "page must be at least 1"))]
+    pub page: Option<u64>,
+
+    #[validate(range(min = 1, max = 100, message = "per_page must be between 1 and 100"))]
+    pub per_page: Option<u64>,
+
+    /// Part of the email address.
+    pub email: Option<String>,
+
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+
+    /// Without a status, deleted users are left out.
+    pub status: Option<UserStatus>,
+}
+
+/// User record as shown to administrators. Secrets such as the password hash
+/// and the TOTP secret are left out.
+pub fn admin_user_json(user: &users::Model, details: Option<&user_details::Model>) -> Value {
+    json!({
+        "id": user.id,
+        "email": user.email,
+        "first_name": details.map(|d| d.first_name.clone()),
+        "last_name": details.map(|d| d.last_name.clone()),
+        "status": UserStatus::of(user),
+        "email_verified_at": user.email_verified_at,
+        "mfa_enabled": user.totp_enabled_at.is_some(),
+        "failed_login_attempts": user.failed_login_attempts,
+        "locked_until": user.locked_until,
+        "suspended_at": user.suspended_at,
+        "suspended_reason": user.suspended_reason,
+        "created_at": user.created_at,
+        "updated_at": user.updated_at,
+        "deleted_at": user.deleted_at,
+    })
+}
+
+/// List users, newest first, with paging and optional filters.
+#[web::get("")]
+pub async fn list_users(
+    query: Result<Query<ListUsersQuery>, QueryPayloadError>,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    let query = query
+        .map_err(|e| AppError::bad_request("invalid_query", e.to_string()))?
+        .into_inner();
+    query.validate()?;
+
+    let page = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(20);
+
+    let mut select = UsersEntity::find();
+
+    if let Some(email) = query.email.as_deref().filter(|email| !email.is_empty()) {
+        select = select.filter(users::Column::Email.contains(email));
+    }
+    if let Some(from) = query.created_from {
+        select = select.filter(users::Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = query.created_to {
+        select = select.filter(users::Column::CreatedAt.lte(to));
+    }
+
+    select = match query.status {
+        None => select.filter(users::Column::DeletedAt.is_null()),
+        Some(UserStatus::Active) => select
+            .filter(users::Column::DeletedAt.is_null())
+            .filter(users::Column::SuspendedAt.is_null()),
+        Some(UserStatus::Suspended) => select
+            .filter(users::Column::DeletedAt.is_null())
+            .filter(users::Column::SuspendedAt.is_not_null()),
+        Some(UserStatus::Deleted) => select.filter(users::Column::DeletedAt.is_not_null()),
+    };
+
+    let paginator = select
+        .find_also_related(UserDetailsEntity)
+        .order_by_desc(users::Column::CreatedAt)
+        .order_by_desc(users::Column::Id)
+        .paginate(db.get_ref(), per_page);
+
+    let total = paginator.num_items().await?;
+    let rows = paginator.fetch_page(page - 1).await?;
+
+    let users: Vec<Value> = rows
+        .iter()
+        .map(|(user, details)| admin_user_json(user, details.as_ref()))
+        .collect();
+
+    Ok(send_success(
+        "Users fetched successfully",
+        json!({
+            "users": users,
+            "page": page,
+            "per_page": per_page,
+            "total": total,
+            "total_pages": total.div_ceil(per_page),
+        }),
+    ))
+}
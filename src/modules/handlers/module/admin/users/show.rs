@@ -0,0 +1,59 @@
+use crate::modules::database::entity::user_details::Entity as UserDetailsEntity;
+use crate::modules::database::entity::user_sessions::{self, Entity as UserSessionsEntity};
+use crate::modules::database::entity::users::Entity as UsersEntity;
+use crate::modules::handlers::module::admin::users::list::admin_user_json;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::rbac::user_role_names;
+use crate::modules::utils::response::send_success;
+use chrono::Utc;
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::types::{Path, State};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use serde_json::json;
+
+/// Show one user with their details, roles and active sessions. Deleted users
+/// can be viewed too, so they can be restored.
+#[web::get("/{id}")]
+pub async fn get_user(
+    id: Path<i32>,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = id.into_inner();
+
+    let (user, details) = UsersEntity::find_by_id(user_id)
+        .find_also_related(UserDetailsEntity)
+        .one(db.get_ref())
+        .await?
+        .ok_or_else(|| AppError::not_found("user_not_found", "User not found"))?;
+
+    let roles = user_role_names(db.get_ref(), user_id).await?;
+
+    // Rotated-out and expired refresh tokens are not sessions anymore
+    let sessions = UserSessionsEntity::find()
+        .filter(user_sessions::Column::UserId.eq(user_id))
+        .filter(user_sessions::Column::RevokedAt.is_null())
+        .filter(user_sessions::Column::ExpiresAt.gt(Utc::now()))
+        .order_by_desc(user_sessions::Column::Id)
+        .all(db.get_ref())
+        .await?;
+
+    let sessions: Vec<_> = sessions
+        .into_iter()
+        .map(|session| {
+            json!({
+                "id": session.id,
+                "family_id": session.family_id,
+                "created_at": session.created_at,
+                "last_seen_at": session.last_seen_at,
+                "expires_at": session.expires_at,
+            })
+        })
+        .collect();
+
+    let mut data = admin_user_json(&user, details.as_ref());
+    data["roles"] = json!(roles);
+    data["sessions"] = json!(sessions);
+
+    Ok(send_success("User fetched successfully", data))
+}
@@ -0,0 +1,147 @@
+use crate::modules::config::AppConfig;
+use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
+use crate::modules::database::entity::users::{
+    self, ActiveModel as UserActiveModel, Entity as UsersEntity,
+};
+use crate::modules::mail::template::Template;
+use crate::modules::mail::{SharedMailer, queue_email};
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::response::send_success;
+use crate::modules::utils::token::{
+    decode_email_verification_token, generate_email_verification_token,
+};
+use chrono::Utc;
+use jsonwebtoken::errors::Error as JwtError;
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::error::JsonPayloadError;
+use ntex::web::types::{Json, State};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, QueryFilter, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct VerifyEmailRequest {
+    #[validate(length(min = 1, message = "token is required"))]
+    pub token: String,
+}
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct ResendVerificationRequest {
+    #[validate(email(message = "invalid email format"))]
+    pub email: String,
+}
+
+/// Sign a verification link for the user and queue it for delivery.
+pub fn queue_verification_email(
+    mailer: &SharedMailer,
+    config: &AppConfig,
+    user_id: i32,
+    email: &str,
+) -> Result<(), JwtError> {
+    let token = generate_email_verification_token(config, user_id, email)?;
+    let verify_link = format!("{}?token={}", config.email_verification_url, token);
+
+    queue_email(
+        mailer,
+        Template::VerifyEmail.render(email, &[("verify_url", &verify_link)]),
+    );
+
+    Ok(())
+}
+
+fn invalid_verification_token() -> AppError {
+    AppError::bad_request(
+        "invalid_verification_token",
+        "Verification token is invalid or has expired",
+    )
+}
+
+#[web::post("/email/verify")]
+pub async fn verify_email(
+    payload: Result<Json<VerifyEmailRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    let claims = decode_email_verification_token(config.get_ref(), &data.token)
+        .map_err(|_| invalid_verification_token())?;
+
+    // The link is only valid for the address it was sent to
+    let user = UsersEntity::find_not_deleted_by_id(claims.sub)
+        .one(db.get_ref())
+        .await?
+        .filter(|user| user.email == claims.email)
+        .ok_or_else(invalid_verification_token)?;
+
+    if user.email_verified_at.is_some() {
+        return Ok(send_success(
+            "Email already verified",
+            json!({ "id": user.id }),
+        ));
+    }
+
+    // Start transaction
+    let txn = db.get_ref().begin().await?;
+
+    let user_id = user.id;
+    let mut active_user: UserActiveModel = user.into();
+    active_user.email_verified_at = Set(Some(Utc::now()));
+    active_user.updated_at = Set(Some(Utc::now()));
+    active_user.update(&txn).await?;
+
+    // Insert audit log into activities table
+    let activity = ActivitiesActiveModel {
+        user_id: Set(user_id),
+        data_id: Set(user_id),
+        data_type: Set("user".to_string()),
+        activity_type: Set(Some("verify_email".to_string())),
+        activity_description: Set(Some("User email verified".to_string())),
+        metadata: Set(Some(json!({ "email": claims.email }))),
+        ..Default::default()
+    };
+
+    activity.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(send_success(
+        "Email verified successfully",
+        json!({ "id": user_id }),
+    ))
+}
+
+#[web::post("/email/verify/resend")]
+pub async fn resend_verification_email(
+    payload: Result<Json<ResendVerificationRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+    mailer: State<SharedMailer>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    // Same response whether or not the email exists, so accounts cannot be enumerated
+    let response = send_success(
+        "If an unverified account exists for this email, a verification link has been sent",
+        json!({}),
+    );
+
+    let user = match UsersEntity::find_not_deleted()
+        .filter(users::Column::Email.eq(data.email.clone()))
+        .one(db.get_ref())
+        .await?
+    {
+        Some(user) if user.email_verified_at.is_none() => user,
+        _ => return Ok(response),
+    };
+
+    queue_verification_email(mailer.get_ref(), config.get_ref(), user.id, &user.email)?;
+
+    Ok(response)
+}
@@ -0,0 +1,3 @@
+pub mod mfa;
+pub mod password;
+pub mod profile;
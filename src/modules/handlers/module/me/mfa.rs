@@ -0,0 +1,271 @@
+use crate::modules::config::AppConfig;
+use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
+use crate::modules::database::entity::mfa_recovery_codes::{
+    self, Entity as MfaRecoveryCodesEntity,
+};
+use crate::modules::database::entity::users::{
+    self, ActiveModel as UserActiveModel, Entity as UsersEntity,
+};
+use crate::modules::utils::auth::AuthUser;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::lockout::{SharedLoginThrottle, register_failed_login};
+use crate::modules::utils::mfa::{
+    generate_recovery_codes, generate_totp_secret, otpauth_uri, replace_recovery_codes,
+    verify_second_factor, verify_totp,
+};
+use crate::modules::utils::request::client_ip;
+use crate::modules::utils::response::send_success;
+use crate::modules::utils::security::verify_password;
+use chrono::Utc;
+use ntex::web;
+use ntex::web::error::JsonPayloadError;
+use ntex::web::types::{Json, State};
+use ntex::web::{HttpRequest, HttpResponse};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct ConfirmTotpRequest {
+    #[validate(length(min = 1, message = "code is required"))]
+    pub code: String,
+}
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct DisableTotpRequest {
+    #[validate(length(min = 1, message = "password is required"))]
+    pub password: String,
+
+    /// A TOTP code or an unused recovery code.
+    #[validate(length(min = 1, message = "code is required"))]
+    pub code: String,
+}
+
+/// Fetch the authenticated user.
+async fn find_user(db: &DatabaseConnection, user_id: i32) -> Result<users::Model, AppError> {
+    UsersEntity::find_not_deleted_by_id(user_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found("user_not_found", "User not found"))
+}
+
+fn mfa_already_enabled() -> AppError {
+    AppError::conflict(
+        "mfa_already_enabled",
+        "Two-factor authentication is already enabled",
+    )
+}
+
+const INVALID_MFA_CODE: &str = "Two-factor code is invalid";
+
+/// Start TOTP enrollment: generate a secret and return it with its otpauth URI.
+/// Two-factor authentication only takes effect once a code is confirmed.
+#[web::post("/mfa/totp")]
+pub async fn enroll_totp(
+    auth: AuthUser,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+) -> Result<HttpResponse, AppError> {
+    let user = find_user(db.get_ref(), auth.user_id).await?;
+
+    if user.totp_enabled_at.is_some() {
+        return Err(mfa_already_enabled());
+    }
+
+    let secret = generate_totp_secret();
+    let uri = otpauth_uri(&secret, &config.mfa_issuer, &user.email)
+        .map_err(|e| AppError::internal("mfa_error", "Failed to start two-factor enrollment", e))?;
+
+    // Enrolling again replaces a secret that was never confirmed
+    let mut active_user: UserActiveModel = user.into();
+    active_user.totp_secret = Set(Some(secret.clone()));
+    active_user.updated_at = Set(Some(Utc::now()));
+    active_user.update(db.get_ref()).await?;
+
+    Ok(send_success(
+        "Scan the URI with an authenticator app, then confirm a code",
+        json!({ "secret": secret, "otpauth_uri": uri }),
+    ))
+}
+
+/// Confirm enrollment with a code from the authenticator app. Enables two-factor
+/// authentication and returns the recovery codes, which are only shown once.
+#[web::post("/mfa/totp/confirm")]
+pub async fn confirm_totp(
+    auth: AuthUser,
+    payload: Result<Json<ConfirmTotpRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    let user = find_user(db.get_ref(), auth.user_id).await?;
+
+    if user.totp_enabled_at.is_some() {
+        return Err(mfa_already_enabled());
+    }
+
+    let step = match user
+        .totp_secret
+        .as_deref()
+        .map(|secret| verify_totp(secret, &data.code, None))
+    {
+        Some(Some(step)) => step,
+        Some(None) => return Err(AppError::bad_request("invalid_mfa_code", INVALID_MFA_CODE)),
+        None => {
+            return Err(AppError::bad_request(
+                "mfa_not_enrolled",
+                "Start two-factor enrollment first",
+            ));
+        }
+    };
+
+    let recovery_codes = generate_recovery_codes();
+
+    // Start transaction
+    let txn = db.get_ref().begin().await?;
+
+    let mut active_user: UserActiveModel = user.into();
+    active_user.totp_enabled_at = Set(Some(Utc::now()));
+    active_user.totp_last_used_step = Set(Some(step));
+    active_user.updated_at = Set(Some(Utc::now()));
+    active_user.update(&txn).await?;
+
+    replace_recovery_codes(&txn, auth.user_id, &recovery_codes).await?;
+
+    // Insert audit log into activities table
+    let activity = ActivitiesActiveModel {
+        user_id: Set(auth.user_id),
+        data_id: Set(auth.user_id),
+        data_type: Set("user".to_string()),
+        activity_type: Set(Some("enable_mfa".to_string())),
+        activity_description: Set(Some("Two-factor authentication enabled".to_string())),
+        metadata: Set(Some(json!({ "method": "totp" }))),
+        ..Default::default()
+    };
+
+    activity.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(send_success(
+        "Two-factor authentication enabled",
+        json!({ "recovery_codes": recovery_codes }),
+    ))
+}
+
+/// Count a wrong password or code like a failed login, so a stolen access token
+/// cannot be used to guess them. Returns the error to respond with.
+async fn failed_attempt(
+    db: &DatabaseConnection,
+    throttle: &SharedLoginThrottle,
+    user: &users::Model,
+    ip: &str,
+    error: AppError,
+) -> Result<AppError, AppError> {
+    throttle.record_ip_failure(ip);
+
+    Ok(
+        match register_failed_login(db, throttle.policy(), user, ip).await? {
+            Some(until) => AppError::Locked { until },
+            None => error,
+        },
+    )
+}
+
+/// Turn two-factor authentication off. Requires the password and a current code.
+#[web::post("/mfa/totp/disable")]
+pub async fn disable_totp(
+    req: HttpRequest,
+    auth: AuthUser,
+    payload: Result<Json<DisableTotpRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+    throttle: State<SharedLoginThrottle>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    let ip = client_ip(req.peer_addr(), req.headers(), config.trusted_proxies);
+    if let Some(retry_after) = throttle.ip_retry_after(&ip) {
+        return Err(AppError::TooManyAttempts { retry_after });
+    }
+
+    let user = find_user(db.get_ref(), auth.user_id).await?;
+
+    if user.totp_enabled_at.is_none() {
+        return Err(AppError::bad_request(
+            "mfa_not_enabled",
+            "Two-factor authentication is not enabled",
+        ));
+    }
+
+    if let Some(until) = user.locked_until.filter(|until| *until > Utc::now()) {
+        return Err(AppError::Locked { until });
+    }
+
+    if !verify_password(&config.password_hasher, &data.password, &user.password).await? {
+        return Err(failed_attempt(
+            db.get_ref(),
+            throttle.get_ref(),
+            &user,
+            &ip,
+            AppError::unauthorized("invalid_credentials", "Password is incorrect"),
+        )
+        .await?);
+    }
+
+    // Start transaction
+    let txn = db.get_ref().begin().await?;
+
+    if verify_second_factor(&txn, &user, &data.code)
+        .await?
+        .is_none()
+    {
+        txn.rollback().await?;
+        return Err(failed_attempt(
+            db.get_ref(),
+            throttle.get_ref(),
+            &user,
+            &ip,
+            AppError::unauthorized("invalid_mfa_code", INVALID_MFA_CODE),
+        )
+        .await?);
+    }
+
+    let mut active_user: UserActiveModel = user.into();
+    active_user.totp_secret = Set(None);
+    active_user.totp_enabled_at = Set(None);
+    active_user.totp_last_used_step = Set(None);
+    active_user.updated_at = Set(Some(Utc::now()));
+    active_user.update(&txn).await?;
+
+    MfaRecoveryCodesEntity::delete_many()
+        .filter(mfa_recovery_codes::Column::UserId.eq(auth.user_id))
+        .exec(&txn)
+        .await?;
+
+    // Insert audit log into activities table
+    let activity = ActivitiesActiveModel {
+        user_id: Set(auth.user_id),
+        data_id: Set(auth.user_id),
+        data_type: Set("user".to_string()),
+        activity_type: Set(Some("disable_mfa".to_string())),
+        activity_description: Set(Some("Two-factor authentication disabled".to_string())),
+        metadata: Set(Some(json!({ "method": "totp" }))),
+        ..Default::default()
+    };
+
+    activity.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(send_success(
+        "Two-factor authentication disabled",
+        json!({}),
+    ))
+}
@@ -0,0 +1,115 @@
+use crate::modules::config::AppConfig;
+use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
+use crate::modules::database::entity::user_details;
+use crate::modules::database::entity::user_sessions::{self, Entity as UserSessionsEntity};
+use crate::modules::database::entity::users::{
+    ActiveModel as UserActiveModel, Entity as UsersEntity,
+};
+use crate::modules::utils::auth::AuthUser;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::password_policy::check_password_policy;
+use crate::modules::utils::response::send_success;
+use crate::modules::utils::security::{hash_password, verify_password};
+use chrono::Utc;
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::error::JsonPayloadError;
+use ntex::web::types::{Json, State};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 1, message = "current_password is required"))]
+    pub current_password: String,
+
+    #[validate(length(min = 1, message = "new_password is required"))]
+    pub new_password: String,
+}
+
+#[web::post("/password")]
+pub async fn change_password(
+    auth: AuthUser,
+    payload: Result<Json<ChangePasswordRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    let (user, details) = UsersEntity::find_not_deleted_by_id(auth.user_id)
+        .find_also_related(user_details::Entity)
+        .one(db.get_ref())
+        .await?
+        .ok_or_else(|| AppError::not_found("user_not_found", "User not found"))?;
+
+    // Verify current password
+    if !verify_password(
+        &config.password_hasher,
+        &data.current_password,
+        &user.password,
+    )
+    .await?
+    {
+        return Err(AppError::unauthorized(
+            "invalid_credentials",
+            "Current password is incorrect",
+        ));
+    }
+
+    let mut personal = vec![user.email.as_str()];
+    if let Some(details) = &details {
+        personal.extend([details.first_name.as_str(), details.last_name.as_str()]);
+    }
+
+    check_password_policy(
+        &config.password_policy,
+        "new_password",
+        &data.new_password,
+        &personal,
+    )?;
+
+    let password_hash = hash_password(&config.password_hasher, &data.new_password).await?;
+
+    // Start transaction
+    let txn = db.get_ref().begin().await?;
+
+    // Store the new password hash
+    let mut active_user: UserActiveModel = user.into();
+    active_user.password = Set(password_hash);
+    active_user.updated_at = Set(Some(Utc::now()));
+    active_user.update(&txn).await?;
+
+    // Revoke every session except the one making this request
+    let revoked = UserSessionsEntity::delete_many()
+        .filter(user_sessions::Column::UserId.eq(auth.user_id))
+        .filter(user_sessions::Column::FamilyId.ne(auth.sid.clone()))
+        .exec(&txn)
+        .await?
+        .rows_affected;
+
+    // Insert audit log into activities table
+    let activity = ActivitiesActiveModel {
+        user_id: Set(auth.user_id),
+        data_id: Set(auth.user_id),
+        data_type: Set("user".to_string()),
+        activity_type: Set(Some("change_password".to_string())),
+        activity_description: Set(Some("User password changed".to_string())),
+        metadata: Set(Some(json!({ "revoked_sessions": revoked }))),
+        ..Default::default()
+    };
+
+    activity.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(send_success(
+        "Password changed successfully",
+        json!({ "revoked_sessions": revoked }),
+    ))
+}
@@ -0,0 +1,126 @@
+use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
+use crate::modules::database::entity::user_details::{self, ActiveModel as UserDetailsActiveModel};
+use crate::modules::database::entity::users::{self, Entity as UsersEntity};
+use crate::modules::utils::auth::AuthUser;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::response::send_success;
+use chrono::Utc;
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::error::JsonPayloadError;
+use ntex::web::types::{Json, State};
+use sea_orm::{ActiveModelTrait, DatabaseConnection, Set, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use validator::Validate;
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct UpdateProfileRequest {
+    #[validate(length(min = 1, message = "first_name is required"))]
+    pub first_name: Option<String>,
+
+    #[validate(length(min = 1, message = "last_name is required"))]
+    pub last_name: Option<String>,
+}
+
+/// Joined `users` + `user_details` record returned by the profile endpoints.
+fn profile_json(user: &users::Model, details: &user_details::Model) -> Value {
+    json!({
+        "id": user.id,
+        "email": user.email,
+        "first_name": details.first_name,
+        "last_name": details.last_name,
+        "created_at": user.created_at,
+        "updated_at": details.updated_at,
+    })
+}
+
+/// Fetch the user together with its details.
+async fn find_profile(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<(users::Model, user_details::Model), AppError> {
+    let profile = UsersEntity::find_not_deleted_by_id(user_id)
+        .find_also_related(user_details::Entity)
+        .one(db)
+        .await?;
+
+    match profile {
+        Some((user, Some(details))) => Ok((user, details)),
+        _ => Err(AppError::not_found("user_not_found", "User not found")),
+    }
+}
+
+#[web::get("")]
+pub async fn get_profile(
+    auth: AuthUser,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    let (user, details) = find_profile(db.get_ref(), auth.user_id).await?;
+
+    Ok(send_success(
+        "Profile fetched successfully",
+        profile_json(&user, &details),
+    ))
+}
+
+#[web::patch("")]
+pub async fn update_profile(
+    auth: AuthUser,
+    payload: Result<Json<UpdateProfileRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    let (user, details) = find_profile(db.get_ref(), auth.user_id).await?;
+
+    let first_name = data
+        .first_name
+        .clone()
+        .unwrap_or_else(|| details.first_name.clone());
+    let last_name = data
+        .last_name
+        .clone()
+        .unwrap_or_else(|| details.last_name.clone());
+
+    // Start transaction
+    let txn = db.get_ref().begin().await?;
+
+    // Update user details
+    let mut active_details: UserDetailsActiveModel = details.clone().into();
+    active_details.first_name = Set(first_name.clone());
+    active_details.last_name = Set(last_name.clone());
+    active_details.updated_at = Set(Some(Utc::now()));
+
+    let updated_details = active_details.update(&txn).await?;
+
+    // Insert audit log into activities table
+    let activity = ActivitiesActiveModel {
+        user_id: Set(user.id),
+        data_id: Set(details.id),
+        data_type: Set("user_details".to_string()),
+        activity_type: Set(Some("update_profile".to_string())),
+        activity_description: Set(Some("User profile updated".to_string())),
+        metadata: Set(Some(json!({
+            "before": {
+                "first_name": details.first_name,
+                "last_name": details.last_name,
+            },
+            "after": {
+                "first_name": first_name,
+                "last_name": last_name,
+            },
+        }))),
+        ..Default::default()
+    };
+
+    activity.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(send_success(
+        "Profile updated successfully",
+        profile_json(&user, &updated_details),
+    ))
+}
@@ -0,0 +1,2 @@
+pub mod forgot;
+pub mod reset;
@@ -0,0 +1,94 @@
+use crate::modules::config::AppConfig;
+use crate::modules::database::entity::password_reset_tokens::{
+    self, ActiveModel as PasswordResetTokensActiveModel, Entity as PasswordResetTokensEntity,
+};
+use crate::modules::database::entity::users::{self, Entity as UsersEntity};
+use crate::modules::mail::template::Template;
+use crate::modules::mail::{SharedMailer, queue_email};
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::response::send_success;
+use crate::modules::utils::security::{generate_secure_token, hash_token};
+use chrono::{Duration, Utc};
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::error::JsonPayloadError;
+use ntex::web::types::{Json, State};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct ForgotPasswordRequest {
+    #[validate(email(message = "invalid email format"))]
+    pub email: String,
+}
+
+#[web::post("/password/forgot")]
+pub async fn forgot_password(
+    payload: Result<Json<ForgotPasswordRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+    mailer: State<SharedMailer>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    // Same response whether or not the email exists, so accounts cannot be enumerated
+    let response = send_success(
+        "If an account exists for this email, a password reset link has been sent",
+        json!({}),
+    );
+
+    // Find user by email
+    let user = match UsersEntity::find_not_deleted()
+        .filter(users::Column::Email.eq(data.email.clone()))
+        .one(db.get_ref())
+        .await?
+    {
+        Some(user) => user,
+        None => return Ok(response),
+    };
+
+    let expire_minutes = config.password_reset_expire_minutes;
+    let token = generate_secure_token();
+
+    // Start transaction
+    let txn = db.get_ref().begin().await?;
+
+    // Only the most recently requested link stays valid
+    PasswordResetTokensEntity::delete_many()
+        .filter(password_reset_tokens::Column::UserId.eq(user.id))
+        .filter(password_reset_tokens::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await?;
+
+    let reset_token = PasswordResetTokensActiveModel {
+        user_id: Set(user.id),
+        token_hash: Set(hash_token(&token)),
+        expires_at: Set(Utc::now() + Duration::minutes(expire_minutes)),
+        ..Default::default()
+    };
+
+    reset_token.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    let reset_link = format!("{}?token={}", config.password_reset_url, token);
+
+    queue_email(
+        mailer.get_ref(),
+        Template::ResetPassword.render(
+            &user.email,
+            &[
+                ("reset_url", &reset_link),
+                ("expire_minutes", &expire_minutes.to_string()),
+            ],
+        ),
+    );
+
+    Ok(response)
+}
@@ -0,0 +1,136 @@
+use crate::modules::config::AppConfig;
+use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
+use crate::modules::database::entity::password_reset_tokens::{
+    self, Entity as PasswordResetTokensEntity,
+};
+use crate::modules::database::entity::user_details;
+use crate::modules::database::entity::user_sessions::{self, Entity as UserSessionsEntity};
+use crate::modules::database::entity::users::{
+    ActiveModel as UserActiveModel, Entity as UsersEntity,
+};
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::password_policy::check_password_policy;
+use crate::modules::utils::response::send_success;
+use crate::modules::utils::security::{hash_password, hash_token};
+use chrono::Utc;
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::error::JsonPayloadError;
+use ntex::web::types::{Json, State};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 1, message = "token is required"))]
+    pub token: String,
+
+    #[validate(length(min = 1, message = "password is required"))]
+    pub password: String,
+}
+
+fn invalid_reset_token() -> AppError {
+    AppError::bad_request(
+        "invalid_reset_token",
+        "Password reset token is invalid or has expired",
+    )
+}
+
+#[web::post("/password/reset")]
+pub async fn reset_password(
+    payload: Result<Json<ResetPasswordRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    // Find an unused, unexpired token by its hash
+    let reset_token = PasswordResetTokensEntity::find()
+        .filter(password_reset_tokens::Column::TokenHash.eq(hash_token(&data.token)))
+        .filter(password_reset_tokens::Column::UsedAt.is_null())
+        .filter(password_reset_tokens::Column::ExpiresAt.gt(Utc::now()))
+        .one(db.get_ref())
+        .await?
+        .ok_or_else(invalid_reset_token)?;
+
+    let (user, details) = UsersEntity::find_not_deleted_by_id(reset_token.user_id)
+        .find_also_related(user_details::Entity)
+        .one(db.get_ref())
+        .await?
+        .ok_or_else(invalid_reset_token)?;
+
+    let mut personal = vec![user.email.as_str()];
+    if let Some(details) = &details {
+        personal.extend([details.first_name.as_str(), details.last_name.as_str()]);
+    }
+
+    check_password_policy(
+        &config.password_policy,
+        "password",
+        &data.password,
+        &personal,
+    )?;
+
+    let password_hash = hash_password(&config.password_hasher, &data.password).await?;
+
+    // Start transaction
+    let txn = db.get_ref().begin().await?;
+
+    // Mark the token as used. Only one concurrent request may consume it.
+    let consumed = PasswordResetTokensEntity::update_many()
+        .col_expr(
+            password_reset_tokens::Column::UsedAt,
+            Expr::value(Utc::now()),
+        )
+        .filter(password_reset_tokens::Column::Id.eq(reset_token.id))
+        .filter(password_reset_tokens::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await?
+        .rows_affected;
+
+    if consumed == 0 {
+        txn.rollback().await?;
+        return Err(invalid_reset_token());
+    }
+
+    // Store the new password hash. Proving control of the email address also
+    // lifts a lockout, which is often why the password is being reset.
+    let user_id = user.id;
+    let mut active_user: UserActiveModel = user.into();
+    active_user.password = Set(password_hash);
+    active_user.failed_login_attempts = Set(0);
+    active_user.locked_until = Set(None);
+    active_user.updated_at = Set(Some(Utc::now()));
+    active_user.update(&txn).await?;
+
+    // Revoke every session, the password may have been compromised
+    let revoked = UserSessionsEntity::delete_many()
+        .filter(user_sessions::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?
+        .rows_affected;
+
+    // Insert audit log into activities table
+    let activity = ActivitiesActiveModel {
+        user_id: Set(user_id),
+        data_id: Set(user_id),
+        data_type: Set("user".to_string()),
+        activity_type: Set(Some("reset_password".to_string())),
+        activity_description: Set(Some("User password reset".to_string())),
+        metadata: Set(Some(json!({ "revoked_sessions": revoked }))),
+        ..Default::default()
+    };
+
+    activity.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(send_success("Password reset successfully", json!({})))
+}
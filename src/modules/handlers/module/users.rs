@@ -1,2 +1,5 @@
 pub mod create;
 pub mod login;
+pub mod login_mfa;
+pub mod logout;
+pub mod refresh;
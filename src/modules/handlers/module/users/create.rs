@@ -1,10 +1,15 @@
+use crate::modules::config::{AppConfig, DeletedEmailRegistration};
 use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
 use crate::modules::database::entity::user_details::ActiveModel as UserDetailsActiveModel;
 use crate::modules::database::entity::users::{self, ActiveModel as UserActiveModel};
-use crate::modules::utils::json::check_json_payload;
-use crate::modules::utils::response::{send_error, send_success};
+use crate::modules::handlers::module::email::verify::queue_verification_email;
+use crate::modules::mail::SharedMailer;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::password_policy::check_password_policy;
+use crate::modules::utils::response::send_success;
 use crate::modules::utils::security::hash_password;
 use ntex::web;
+use ntex::web::HttpResponse;
 use ntex::web::error::JsonPayloadError;
 use ntex::web::types::{Json, State};
 use sea_orm::TransactionTrait;
@@ -18,7 +23,7 @@ pub struct CreateUserRequest {
     #[validate(email(message = "invalid email format"))]
     pub email: String,
 
-    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
+    #[validate(length(min = 1, message = "password is required"))]
     pub password: String,
 
     #[validate(length(min = 1, message = "first_name is required"))]
@@ -32,71 +37,49 @@ pub struct CreateUserRequest {
 pub async fn create_user(
     payload: Result<Json<CreateUserRequest>, JsonPayloadError>,
     db: State<DatabaseConnection>,
-) -> impl web::Responder {
-    // Handle JSON parsing errors
-    let data = match check_json_payload(payload) {
-        Ok(v) => v,
-        Err(resp) => return resp,
+    config: State<AppConfig>,
+    mailer: State<SharedMailer>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    check_password_policy(
+        &config.password_policy,
+        "password",
+        &data.password,
+        &[&data.email, &data.first_name, &data.last_name],
+    )?;
+
+    // Check if user already exists. A soft-deleted account only keeps its email
+    // address taken when DELETED_EMAIL_REGISTRATION is reject.
+    let lookup = match config.deleted_email_registration {
+        DeletedEmailRegistration::Reject => users::Entity::find(),
+        DeletedEmailRegistration::Allow => users::Entity::find_not_deleted(),
     };
 
-    // Run validation when JSON was parsed successfully
-    if let Err(errors) = data.validate() {
-        return send_error(422, "validation_error", "Validation failed", Some(errors));
-    }
-
-    // Check if user already exists
-    let existing = match users::Entity::find()
+    let existing = lookup
         .filter(users::Column::Email.eq(data.email.clone()))
         .one(db.get_ref())
-        .await
-    {
-        Ok(user) => user,
-        Err(_) => {
-            return send_error(500, "db_error", "Database error", Option::<()>::None);
-        }
-    };
+        .await?;
 
     if existing.is_some() {
-        return send_error(
-            400,
-            "user_exists",
-            "User already exists",
-            Option::<()>::None,
-        );
+        return Err(AppError::bad_request("user_exists", "User already exists"));
     }
 
+    // Hash the password before opening the transaction, it is the slowest step
+    let password_hash = hash_password(&config.password_hasher, &data.password).await?;
+
     // Start transaction
-    let txn = match db.get_ref().begin().await {
-        Ok(txn) => txn,
-        Err(_) => {
-            return send_error(
-                500,
-                "db_error",
-                "Failed to start transaction",
-                Option::<()>::None,
-            );
-        }
-    };
+    let txn = db.get_ref().begin().await?;
 
     // Insert new user
     let new_user = UserActiveModel {
         email: Set(data.email.clone()),
-        password: Set(hash_password(&data.password)),
+        password: Set(password_hash),
         ..Default::default()
     };
 
-    let inserted_user = match new_user.insert(&txn).await {
-        Ok(user) => user,
-        Err(_) => {
-            let _ = txn.rollback().await;
-            return send_error(
-                500,
-                "insert_failed",
-                "Failed to create user",
-                Option::<()>::None,
-            );
-        }
-    };
+    let inserted_user = new_user.insert(&txn).await?;
 
     // Insert user details
     let new_details = UserDetailsActiveModel {
@@ -106,15 +89,7 @@ pub async fn create_user(
         ..Default::default()
     };
 
-    if (new_details.insert(&txn).await).is_err() {
-        let _ = txn.rollback().await;
-        return send_error(
-            500,
-            "insert_failed",
-            "Failed to create user details",
-            Option::<()>::None,
-        );
-    }
+    new_details.insert(&txn).await?;
 
     // Insert audit log into activities table
     let activity = ActivitiesActiveModel {
@@ -131,20 +106,23 @@ pub async fn create_user(
         ..Default::default()
     };
 
-    if (activity.insert(&txn).await).is_err() {
-        let _ = txn.rollback().await;
-        return send_error(
-            500,
-            "insert_failed",
-            "Failed to create activity log",
-            Option::<()>::None,
-        );
-    }
+    activity.insert(&txn).await?;
 
-    let _ = txn.commit().await;
+    txn.commit().await?;
+
+    // Send the signed verification link. The account is already created, so a
+    // failure here is not fatal: the user can ask for the link again.
+    if let Err(e) = queue_verification_email(
+        mailer.get_ref(),
+        config.get_ref(),
+        inserted_user.id,
+        &inserted_user.email,
+    ) {
+        tracing::error!(error = %e, "Failed to send verification email");
+    }
 
-    send_success(
+    Ok(send_success(
         "User created successfully",
         serde_json::json!({ "id": inserted_user.id }),
-    )
+    ))
 }
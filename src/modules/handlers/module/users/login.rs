@@ -1,12 +1,27 @@
+use crate::modules::config::AppConfig;
 use crate::modules::database::entity::user_details::{self, Entity as UserDetailsEntity};
+use crate::modules::database::entity::user_sessions::ActiveModel as UserSessionsActiveModel;
 use crate::modules::database::entity::users::{self, Entity as UsersEntity};
-use crate::modules::utils::json::check_json_payload;
-use crate::modules::utils::response::{send_error, send_success};
-use crate::modules::utils::security::verify_password;
+use crate::modules::metrics::metrics;
+use crate::modules::utils::cookie::refresh_token_cookie;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::lockout::{
+    SharedLoginThrottle, register_failed_login, reset_failed_logins,
+};
+use crate::modules::utils::rbac::user_role_names;
+use crate::modules::utils::request::client_ip;
+use crate::modules::utils::response::send_success;
+use crate::modules::utils::security::{hash_password, verify_password};
+use crate::modules::utils::token::{
+    generate_access_token, generate_mfa_challenge_token, generate_refresh_token,
+};
+use chrono::Utc;
 use ntex::web;
 use ntex::web::error::JsonPayloadError;
 use ntex::web::types::{Json, State};
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use ntex::web::{HttpRequest, HttpResponse};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
@@ -19,183 +34,178 @@ pub struct LoginUserRequest {
     pub password: String,
 }
 
-#[derive(Serialize)]
-struct Claims {
-    sub: i32,
-    user_id: i32,
-    email: String,
-    exp: usize,
-    iat: usize,
-    jti: String,
+/// Error for an account suspended by an administrator.
+pub fn account_suspended() -> AppError {
+    AppError::forbidden("account_suspended", "Account has been suspended")
 }
 
-fn generate_access_token(user_id: i32, email: &str) -> Result<String, String> {
-    use chrono::{Duration, Utc};
-    use jsonwebtoken::{EncodingKey, Header, encode};
-    use std::env;
-
-    // Get JWT secret from environment
-    let jwt_secret = env::var("JWT_SECRET").map_err(|_| "JWT_SECRET not set".to_string())?;
-
-    // Get expiration minutes from env, default to 15 if not set or invalid
-    let expire_minutes = env::var("ACCESS_TOKEN_EXPIRE_MINUTES")
-        .ok()
-        .and_then(|v| v.parse::<i64>().ok())
-        .unwrap_or(15);
-
-    // Generate expiration timestamp
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::minutes(expire_minutes))
-        .expect("valid timestamp")
-        .timestamp() as usize;
-
-    let claims = Claims {
-        sub: user_id,
-        user_id,
-        email: email.to_string(),
-        exp: expiration,
-        iat: Utc::now().timestamp() as usize,
-        jti: uuid::Uuid::new_v4().to_string(),
-    };
-
-    match encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(jwt_secret.as_ref()),
-    ) {
-        Ok(token) => Ok(token),
-        Err(_) => Err("Failed to generate token".to_string()),
-    }
-}
-
-fn generate_refresh_token(user_id: i32, email: &str) -> Result<String, String> {
-    use chrono::{Duration, Utc};
-    use jsonwebtoken::{EncodingKey, Header, encode};
-    use std::env;
-
-    // Get JWT secret from environment
-    let jwt_secret = env::var("JWT_SECRET").map_err(|_| "JWT_SECRET not set".to_string())?;
-
-    // Generate expiration timestamp (7 days)
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::days(7))
-        .expect("valid timestamp")
-        .timestamp() as usize;
-
-    let claims = Claims {
-        sub: user_id,
-        email: email.to_string(),
-        exp: expiration,
-        iat: Utc::now().timestamp() as usize,
-        jti: uuid::Uuid::new_v4().to_string(),
-        user_id,
-    };
-
-    match encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(jwt_secret.as_ref()),
-    ) {
-        Ok(token) => Ok(token),
-        Err(_) => Err("Failed to generate token".to_string()),
-    }
+fn invalid_credentials() -> AppError {
+    AppError::unauthorized("invalid_credentials", "Invalid email or password")
 }
 
 #[web::post("/login")]
 pub async fn login_user(
+    req: HttpRequest,
     payload: Result<Json<LoginUserRequest>, JsonPayloadError>,
     db: State<DatabaseConnection>,
-) -> impl web::Responder {
-    // Handle JSON parsing errors
-    let data = match check_json_payload(payload) {
-        Ok(v) => v,
-        Err(resp) => return resp,
-    };
+    config: State<AppConfig>,
+    throttle: State<SharedLoginThrottle>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    password_login(
+        &req,
+        data,
+        db.get_ref(),
+        config.get_ref(),
+        throttle.get_ref(),
+    )
+    .await
+    .inspect_err(|err| metrics().login_failed(err.code()))
+}
 
-    // Run validation when JSON was parsed successfully
-    if let Err(errors) = data.validate() {
-        return send_error(422, "validation_error", "Validation failed", Some(errors));
+/// Check the email and password, then start a session or, with two-factor
+/// authentication on, hand out the challenge for the second step.
+async fn password_login(
+    req: &HttpRequest,
+    data: LoginUserRequest,
+    db: &DatabaseConnection,
+    config: &AppConfig,
+    throttle: &SharedLoginThrottle,
+) -> Result<HttpResponse, AppError> {
+    // Slow down clients that keep failing, whichever accounts they try
+    let ip = client_ip(req.peer_addr(), req.headers(), config.trusted_proxies);
+    if let Some(retry_after) = throttle.ip_retry_after(&ip) {
+        return Err(AppError::TooManyAttempts { retry_after });
     }
 
     // Find user by email
-    let user = match UsersEntity::find()
+    let user = match UsersEntity::find_not_deleted()
         .filter(users::Column::Email.eq(data.email.clone()))
-        .one(db.get_ref())
-        .await
+        .one(db)
+        .await?
     {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            return send_error(
-                401,
-                "invalid_credentials",
-                "Invalid email or password",
-                Option::<()>::None,
-            );
-        }
-        Err(_) => {
-            return send_error(500, "db_error", "Database error", Option::<()>::None);
+        Some(user) => user,
+        None => {
+            throttle.record_ip_failure(&ip);
+            return Err(invalid_credentials());
         }
     };
 
+    // Refuse locked accounts before looking at the password
+    if let Some(until) = user.locked_until.filter(|until| *until > Utc::now()) {
+        return Err(AppError::Locked { until });
+    }
+
     // Verify password
-    if !verify_password(&data.password, &user.password) {
-        return send_error(
-            401,
-            "invalid_credentials",
-            "Invalid email or password",
-            Option::<()>::None,
+    if !verify_password(&config.password_hasher, &data.password, &user.password).await? {
+        throttle.record_ip_failure(&ip);
+
+        return Err(
+            match register_failed_login(db, throttle.policy(), &user, &ip).await? {
+                Some(until) => AppError::Locked { until },
+                None => invalid_credentials(),
+            },
         );
     }
 
+    // Quietly upgrade hashes stored with bcrypt or weaker Argon2 parameters. The
+    // login does not depend on it, the upgrade is retried on the next login.
+    if config.password_hasher.needs_rehash(&user.password)
+        && let Ok(password_hash) = hash_password(&config.password_hasher, &data.password).await
+    {
+        let _ = UsersEntity::update_many()
+            .col_expr(users::Column::Password, Expr::value(password_hash))
+            .filter(users::Column::Id.eq(user.id))
+            .exec(db)
+            .await;
+    }
+
+    // Optionally block accounts that never confirmed their email address
+    if config.require_email_verification && user.email_verified_at.is_none() {
+        return Err(AppError::forbidden(
+            "email_not_verified",
+            "Email address has not been verified",
+        ));
+    }
+
+    if user.suspended_at.is_some() {
+        return Err(account_suspended());
+    }
+
+    // With two-factor authentication on, the password alone only earns a
+    // short-lived challenge token to be exchanged at /login/mfa
+    if user.totp_enabled_at.is_some() {
+        let mfa_token = generate_mfa_challenge_token(config, user.id)?;
+        return Ok(send_success(
+            "Two-factor authentication required",
+            serde_json::json!({ "mfa_required": true, "mfa_token": mfa_token }),
+        ));
+    }
+
+    start_session(db, config, &user).await
+}
+
+/// Start a new session for a fully authenticated user: sign the tokens, store the
+/// session and return the login response with the refresh token cookie.
+pub async fn start_session(
+    db: &DatabaseConnection,
+    config: &AppConfig,
+    user: &users::Model,
+) -> Result<HttpResponse, AppError> {
+    // Failed attempts only count as cleared once every factor was provided. A
+    // correct password alone must not reset them, or the TOTP code could be
+    // guessed without ever locking the account.
+    reset_failed_logins(db, user).await?;
+
     // Fetch user details
-    let details = match UserDetailsEntity::find()
+    let details = UserDetailsEntity::find()
         .filter(user_details::Column::UserId.eq(user.id))
-        .one(db.get_ref())
-        .await
-    {
-        Ok(Some(details)) => details,
-        _ => {
-            return send_error(
-                500,
+        .one(db)
+        .await?
+        .ok_or_else(|| {
+            AppError::internal(
                 "db_error",
                 "Failed to fetch user details",
-                Option::<()>::None,
-            );
-        }
-    };
+                format!("user {} has no user_details row", user.id),
+            )
+        })?;
 
-    let access_token = match generate_access_token(user.id, &user.email) {
-        Ok(token) => token,
-        Err(msg) => {
-            return send_error(500, "token_error", &msg, Option::<()>::None);
-        }
-    };
+    let roles = user_role_names(db, user.id).await?;
 
-    // Generate refresh token (long-lived, 7 days)
-    let _refresh_token = match generate_refresh_token(user.id, &user.email) {
-        Ok(token) => token,
-        Err(msg) => {
-            return send_error(500, "token_error", &msg, Option::<()>::None);
-        }
-    };
+    // Every login starts a new session family; rotated refresh tokens stay in it
+    let family_id = uuid::Uuid::new_v4().to_string();
 
-    let session_mode =
-        std::env::var("SESSION_MODE").unwrap_or_else(|_| "jwt_stateless".to_string());
+    let access_token = generate_access_token(config, user.id, &user.email, &family_id, &roles)?;
 
-    // If session mode is "jwt_server_stateful", store JTI in UserSession table
-    if session_mode == "jwt_server_stateful" {
-        // Here you would typically store the JTI in the database associated with the user
-        // For brevity, this part is omitted
-        // TODO: Implement storing JTI in UserSession table
-    }
+    // Generate refresh token (long-lived, REFRESH_TOKEN_EXPIRE_DAYS)
+    let refresh_token = generate_refresh_token(config, user.id, &user.email, &family_id)?;
+
+    // Store the refresh token JTI in user_sessions. This is needed in every session
+    // mode, because refreshing an access token is only allowed for a known session.
+    let session = UserSessionsActiveModel {
+        user_id: Set(user.id),
+        jti: Set(refresh_token.jti.clone()),
+        family_id: Set(family_id),
+        expires_at: Set(refresh_token.expires_at),
+        ..Default::default()
+    };
 
-    // Create cookie called refresh_token with HttpOnly and Secure flags
-    // Note: In a real application, you would set this cookie in the HTTP response headers
-    // For brevity, this part is omitted
-    // TODO: Set refresh_token cookie in response, maybe using ntex-session?
+    session.insert(db).await?;
 
-    send_success(
+    let mut response = send_success(
         "Login successful",
         serde_json::json!({ "id": user.id, "email": user.email, "first_name": details.first_name, "last_name": details.last_name, "access_token": access_token }),
-    )
+    );
+
+    // Send the refresh token as an HttpOnly cookie so it is never exposed to scripts
+    let cookie = refresh_token_cookie(&refresh_token.token, config.refresh_token_lifetime);
+    response
+        .add_cookie(cookie)
+        .map_err(|e| AppError::internal("cookie_error", "Failed to set refresh token cookie", e))?;
+
+    metrics().login_succeeded();
+
+    Ok(response)
 }
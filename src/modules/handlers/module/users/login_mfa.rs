@@ -0,0 +1,128 @@
+use crate::modules::config::AppConfig;
+use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
+use crate::modules::database::entity::users::Entity as UsersEntity;
+use crate::modules::handlers::module::users::login::{account_suspended, start_session};
+use crate::modules::metrics::metrics;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::lockout::{SharedLoginThrottle, register_failed_login};
+use crate::modules::utils::mfa::{SecondFactor, remaining_recovery_codes, verify_second_factor};
+use crate::modules::utils::request::client_ip;
+use crate::modules::utils::token::decode_mfa_challenge_token;
+use chrono::Utc;
+use ntex::web;
+use ntex::web::error::JsonPayloadError;
+use ntex::web::types::{Json, State};
+use ntex::web::{HttpRequest, HttpResponse};
+use sea_orm::{ActiveModelTrait, DatabaseConnection, Set};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+#[derive(Deserialize, Serialize, Validate)]
+pub struct LoginMfaRequest {
+    #[validate(length(min = 1, message = "mfa_token is required"))]
+    pub mfa_token: String,
+
+    /// A TOTP code or an unused recovery code.
+    #[validate(length(min = 1, message = "code is required"))]
+    pub code: String,
+}
+
+/// Second login step for accounts with two-factor authentication: exchange the
+/// challenge token from `login_user` and a code for a session.
+#[web::post("/login/mfa")]
+pub async fn login_mfa(
+    req: HttpRequest,
+    payload: Result<Json<LoginMfaRequest>, JsonPayloadError>,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+    throttle: State<SharedLoginThrottle>,
+) -> Result<HttpResponse, AppError> {
+    let data = payload?.into_inner();
+    data.validate()?;
+
+    second_factor_login(
+        &req,
+        data,
+        db.get_ref(),
+        config.get_ref(),
+        throttle.get_ref(),
+    )
+    .await
+    .inspect_err(|err| metrics().login_failed(err.code()))
+}
+
+async fn second_factor_login(
+    req: &HttpRequest,
+    data: LoginMfaRequest,
+    db: &DatabaseConnection,
+    config: &AppConfig,
+    throttle: &SharedLoginThrottle,
+) -> Result<HttpResponse, AppError> {
+    // Codes are short, so guessing them is throttled like passwords
+    let ip = client_ip(req.peer_addr(), req.headers(), config.trusted_proxies);
+    if let Some(retry_after) = throttle.ip_retry_after(&ip) {
+        return Err(AppError::TooManyAttempts { retry_after });
+    }
+
+    let invalid_mfa_token = || {
+        AppError::unauthorized(
+            "invalid_mfa_token",
+            "Two-factor challenge is invalid or has expired, log in again",
+        )
+    };
+
+    let claims =
+        decode_mfa_challenge_token(config, &data.mfa_token).map_err(|_| invalid_mfa_token())?;
+
+    let user = UsersEntity::find_not_deleted_by_id(claims.sub)
+        .one(db)
+        .await?
+        .filter(|user| user.totp_enabled_at.is_some())
+        .ok_or_else(invalid_mfa_token)?;
+
+    if let Some(until) = user.locked_until.filter(|until| *until > Utc::now()) {
+        return Err(AppError::Locked { until });
+    }
+
+    if user.suspended_at.is_some() {
+        return Err(account_suspended());
+    }
+
+    let factor = match verify_second_factor(db, &user, &data.code).await? {
+        Some(factor) => factor,
+        None => {
+            throttle.record_ip_failure(&ip);
+
+            return Err(
+                match register_failed_login(db, throttle.policy(), &user, &ip).await? {
+                    Some(until) => AppError::Locked { until },
+                    None => {
+                        AppError::unauthorized("invalid_mfa_code", "Two-factor code is invalid")
+                    }
+                },
+            );
+        }
+    };
+
+    // A used recovery code usually means a lost authenticator, keep a trace of it
+    if let SecondFactor::RecoveryCode = factor {
+        let remaining = remaining_recovery_codes(db, user.id)
+            .await
+            .unwrap_or_default();
+
+        let activity = ActivitiesActiveModel {
+            user_id: Set(user.id),
+            data_id: Set(user.id),
+            data_type: Set("user".to_string()),
+            activity_type: Set(Some("use_recovery_code".to_string())),
+            activity_description: Set(Some("Logged in with a recovery code".to_string())),
+            metadata: Set(Some(json!({ "ip": ip, "remaining_codes": remaining }))),
+            ..Default::default()
+        };
+
+        activity.insert(db).await?;
+    }
+
+    start_session(db, config, &user).await
+}
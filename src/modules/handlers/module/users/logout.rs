@@ -0,0 +1,49 @@
+use crate::modules::database::entity::user_sessions::{self, Entity as UserSessionsEntity};
+use crate::modules::utils::auth::AuthUser;
+use crate::modules::utils::cookie::clear_refresh_token_cookie;
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::response::send_success;
+use ntex::web;
+use ntex::web::HttpResponse;
+use ntex::web::types::State;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde_json::json;
+
+/// Success response that also clears the refresh token cookie.
+fn logged_out(message: &str, revoked_sessions: u64) -> HttpResponse {
+    let mut response = send_success(message, json!({ "revoked_sessions": revoked_sessions }));
+    let _ = response.add_cookie(clear_refresh_token_cookie());
+    response
+}
+
+#[web::post("")]
+pub async fn logout_user(
+    auth: AuthUser,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    // Delete every row of the current session family, including rotated-out JTIs
+    let result = UserSessionsEntity::delete_many()
+        .filter(user_sessions::Column::UserId.eq(auth.user_id))
+        .filter(user_sessions::Column::FamilyId.eq(auth.sid.clone()))
+        .exec(db.get_ref())
+        .await?;
+
+    Ok(logged_out("Logout successful", result.rows_affected))
+}
+
+#[web::post("/all")]
+pub async fn logout_all(
+    auth: AuthUser,
+    db: State<DatabaseConnection>,
+) -> Result<HttpResponse, AppError> {
+    // Revoke every session of the user on every device
+    let result = UserSessionsEntity::delete_many()
+        .filter(user_sessions::Column::UserId.eq(auth.user_id))
+        .exec(db.get_ref())
+        .await?;
+
+    Ok(logged_out(
+        "Logged out from all sessions",
+        result.rows_affected,
+    ))
+}
@@ -0,0 +1,216 @@
+use crate::modules::config::AppConfig;
+use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
+use crate::modules::database::entity::user_sessions::{
+    self, ActiveModel as UserSessionsActiveModel, Entity as UserSessionsEntity,
+};
+use crate::modules::database::entity::users::Entity as UsersEntity;
+use crate::modules::utils::cookie::{
+    REFRESH_TOKEN_COOKIE, clear_refresh_token_cookie, refresh_token_cookie,
+};
+use crate::modules::utils::error::AppError;
+use crate::modules::utils::rbac::user_role_names;
+use crate::modules::utils::response::{send_error, send_success};
+use crate::modules::utils::token::{
+    TokenType, decode_token, generate_access_token, generate_refresh_token,
+};
+use chrono::Utc;
+use ntex::http::HttpMessage;
+use ntex::web;
+use ntex::web::types::State;
+use ntex::web::{HttpRequest, HttpResponse};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde_json::json;
+
+/// Error response for a refresh token that can no longer be used. The cookie is
+/// cleared as well, so the client stops presenting it.
+fn invalid_refresh_token(code: &str, message: &str) -> HttpResponse {
+    let mut response = send_error(401, code, message, Option::<()>::None);
+    let _ = response.add_cookie(clear_refresh_token_cookie());
+    response
+}
+
+/// Revoke every session in the family of a refresh token that was presented after
+/// it had already been rotated out, and record the incident in `activities`.
+async fn revoke_reused_family(
+    db: &DatabaseConnection,
+    session: &user_sessions::Model,
+) -> Result<(), sea_orm::DbErr> {
+    let txn = db.begin().await?;
+
+    UserSessionsEntity::update_many()
+        .col_expr(user_sessions::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(user_sessions::Column::FamilyId.eq(session.family_id.clone()))
+        .filter(user_sessions::Column::RevokedAt.is_null())
+        .exec(&txn)
+        .await?;
+
+    let activity = ActivitiesActiveModel {
+        user_id: Set(session.user_id),
+        data_id: Set(session.user_id),
+        data_type: Set("user".to_string()),
+        activity_type: Set(Some("refresh_token_reuse".to_string())),
+        activity_description: Set(Some(
+            "Rotated refresh token was reused, session family revoked".to_string(),
+        )),
+        metadata: Set(Some(json!({
+            "family_id": session.family_id,
+            "jti": session.jti,
+        }))),
+        ..Default::default()
+    };
+
+    activity.insert(&txn).await?;
+
+    txn.commit().await
+}
+
+#[web::post("/token/refresh")]
+pub async fn refresh_token(
+    req: HttpRequest,
+    db: State<DatabaseConnection>,
+    config: State<AppConfig>,
+) -> Result<HttpResponse, AppError> {
+    // Read refresh token from the HttpOnly cookie set on login
+    let token = match req.cookie(REFRESH_TOKEN_COOKIE) {
+        Some(cookie) => cookie.value().to_string(),
+        None => {
+            return Err(AppError::unauthorized(
+                "missing_refresh_token",
+                "Refresh token is missing",
+            ));
+        }
+    };
+
+    // Rejected tokens are answered with Ok, because the response also has to
+    // clear the cookie
+
+    // Verify signature and expiry
+    let claims = match decode_token(config.get_ref(), &token, TokenType::Refresh) {
+        Ok(claims) => claims,
+        Err(_) => {
+            return Ok(invalid_refresh_token(
+                "invalid_refresh_token",
+                "Invalid or expired token",
+            ));
+        }
+    };
+
+    // Look up the session the token was issued for
+    let session = match UserSessionsEntity::find()
+        .filter(user_sessions::Column::Jti.eq(claims.jti.clone()))
+        .one(db.get_ref())
+        .await?
+    {
+        Some(session) => session,
+        None => {
+            return Ok(invalid_refresh_token(
+                "invalid_refresh_token",
+                "Invalid or expired token",
+            ));
+        }
+    };
+
+    // A revoked token being presented again means it was rotated out and replayed
+    if session.revoked_at.is_some() {
+        revoke_reused_family(db.get_ref(), &session).await?;
+
+        return Ok(invalid_refresh_token(
+            "refresh_token_reused",
+            "Refresh token has already been used",
+        ));
+    }
+
+    if session.expires_at < Utc::now() {
+        return Ok(invalid_refresh_token(
+            "invalid_refresh_token",
+            "Invalid or expired token",
+        ));
+    }
+
+    let user = match UsersEntity::find_not_deleted_by_id(session.user_id)
+        .one(db.get_ref())
+        .await?
+    {
+        Some(user) => user,
+        None => {
+            return Ok(invalid_refresh_token(
+                "invalid_refresh_token",
+                "Invalid or expired token",
+            ));
+        }
+    };
+
+    if user.suspended_at.is_some() {
+        return Ok(invalid_refresh_token(
+            "account_suspended",
+            "Account has been suspended",
+        ));
+    }
+
+    // Roles are looked up again, so granted or revoked roles apply from the next refresh
+    let roles = user_role_names(db.get_ref(), user.id).await?;
+
+    let access_token = generate_access_token(
+        config.get_ref(),
+        user.id,
+        &user.email,
+        &session.family_id,
+        &roles,
+    )?;
+
+    let new_refresh_token =
+        generate_refresh_token(config.get_ref(), user.id, &user.email, &session.family_id)?;
+
+    // Start transaction
+    let txn = db.get_ref().begin().await?;
+
+    // Rotate out the old JTI. Only one concurrent request may win this update,
+    // any other one is treated like a replay of the same token.
+    let rotated = UserSessionsEntity::update_many()
+        .col_expr(user_sessions::Column::RevokedAt, Expr::value(Utc::now()))
+        .col_expr(user_sessions::Column::LastSeenAt, Expr::value(Utc::now()))
+        .filter(user_sessions::Column::Id.eq(session.id))
+        .filter(user_sessions::Column::RevokedAt.is_null())
+        .exec(&txn)
+        .await?
+        .rows_affected;
+
+    if rotated == 0 {
+        txn.rollback().await?;
+        revoke_reused_family(db.get_ref(), &session).await?;
+
+        return Ok(invalid_refresh_token(
+            "refresh_token_reused",
+            "Refresh token has already been used",
+        ));
+    }
+
+    // Store the new JTI in the same session family
+    let new_session = UserSessionsActiveModel {
+        user_id: Set(user.id),
+        jti: Set(new_refresh_token.jti.clone()),
+        family_id: Set(session.family_id.clone()),
+        expires_at: Set(new_refresh_token.expires_at),
+        ..Default::default()
+    };
+
+    new_session.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    let mut response = send_success(
+        "Token refreshed successfully",
+        json!({ "access_token": access_token }),
+    );
+
+    let cookie = refresh_token_cookie(&new_refresh_token.token, config.refresh_token_lifetime);
+    response
+        .add_cookie(cookie)
+        .map_err(|e| AppError::internal("cookie_error", "Failed to set refresh token cookie", e))?;
+
+    Ok(response)
+}
@@ -0,0 +1,17 @@
+use tracing_subscriber::EnvFilter;
+
+/// Install the global subscriber that writes one JSON object per line to stdout.
+///
+/// Levels are read from `RUST_LOG` (default `info,sqlx=warn`). Lines written while a
+/// request is handled carry its `request_id` in the `span` object.
+pub fn init_logging() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info,sqlx=warn"));
+
+    tracing_subscriber::fmt()
+        .json()
+        .with_env_filter(filter)
+        .with_current_span(true)
+        .with_span_list(false)
+        .init();
+}
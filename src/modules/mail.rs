@@ -0,0 +1,123 @@
+pub mod file;
+pub mod log;
+pub mod noop;
+pub mod smtp;
+pub mod template;
+
+use async_trait::async_trait;
+use lettre::Message;
+use lettre::message::{Mailbox, MultiPart};
+use ntex::rt::System;
+use ntex::time::{Millis, Seconds, sleep};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// An outgoing email with both an HTML and a plain-text body.
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+/// Outbound mail transport.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), String>;
+
+    /// Check that mail could be delivered right now, for the readiness probe.
+    async fn check(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Mailer shared between workers through ntex `State`.
+pub type SharedMailer = Arc<dyn Mailer>;
+
+/// Transport selected by `MAIL_TRANSPORT`. Only defaults to `log` when `ENV` is
+/// `development`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MailTransport {
+    Smtp,
+    File,
+    Log,
+    Noop,
+}
+
+impl FromStr for MailTransport {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "smtp" => Ok(MailTransport::Smtp),
+            "file" => Ok(MailTransport::File),
+            "log" => Ok(MailTransport::Log),
+            "noop" => Ok(MailTransport::Noop),
+            _ => Err("must be smtp, file, log or noop".to_string()),
+        }
+    }
+}
+
+/// Number of queued emails not sent yet.
+static QUEUED_EMAILS: AtomicUsize = AtomicUsize::new(0);
+
+/// Queue an email for delivery in the background, so the request never waits on it.
+///
+/// Delivery runs on the main arbiter rather than the worker handling the request,
+/// so emails queued by the last requests survive the workers being stopped.
+pub fn queue_email(mailer: &SharedMailer, email: Email) {
+    let mailer = mailer.clone();
+    QUEUED_EMAILS.fetch_add(1, Ordering::SeqCst);
+
+    // Keep the request's span so failures are logged with its request ID
+    System::current().arbiter().spawn(
+        async move {
+            if let Err(msg) = mailer.send(&email).await {
+                tracing::error!(
+                    subject = %email.subject,
+                    to = %email.to,
+                    error = %msg,
+                    "Failed to send email"
+                );
+            }
+            QUEUED_EMAILS.fetch_sub(1, Ordering::SeqCst);
+        }
+        .in_current_span(),
+    );
+}
+
+/// Wait until every queued email was handed to the transport, for at most
+/// `timeout`. Returns whether the queue is empty.
+pub async fn wait_for_queued_emails(timeout: Seconds) -> bool {
+    let deadline = Instant::now() + Duration::from(timeout);
+
+    while QUEUED_EMAILS.load(Ordering::SeqCst) > 0 {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        sleep(Millis(50)).await;
+    }
+
+    true
+}
+
+/// Build a multipart/alternative message for the transports backed by lettre.
+fn build_message(from: &Mailbox, email: &Email) -> Result<Message, String> {
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|_| format!("Invalid recipient address: {}", email.to))?;
+
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(email.subject.clone())
+        .multipart(MultiPart::alternative_plain_html(
+            email.text_body.clone(),
+            email.html_body.clone(),
+        ))
+        .map_err(|e| format!("Failed to build email: {}", e))
+}
@@ -0,0 +1,46 @@
+use super::{Email, Mailer, build_message};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+/// Writes every email as an `.eml` file into `MAIL_FILE_DIR` (default `mail`).
+/// Meant for development, the files can be opened with any mail client.
+pub struct FileMailer {
+    dir: String,
+    transport: AsyncFileTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl FileMailer {
+    pub fn new(dir: &str, from: Mailbox) -> Result<Self, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create mail directory {}: {}", dir, e))?;
+
+        Ok(Self {
+            transport: AsyncFileTransport::new(dir),
+            dir: dir.to_string(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), String> {
+        let message = build_message(&self.from, email)?;
+
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Failed to write email file: {}", e))
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        match std::fs::metadata(&self.dir) {
+            Ok(meta) if meta.is_dir() && !meta.permissions().readonly() => Ok(()),
+            Ok(_) => Err(format!("{} is not a writable directory", self.dir)),
+            Err(e) => Err(format!("Mail directory {} unavailable: {}", self.dir, e)),
+        }
+    }
+}
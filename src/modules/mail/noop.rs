@@ -0,0 +1,12 @@
+use super::{Email, Mailer};
+use async_trait::async_trait;
+
+/// Discards every email. Useful for tests.
+pub struct NoopMailer;
+
+#[async_trait]
+impl Mailer for NoopMailer {
+    async fn send(&self, _: &Email) -> Result<(), String> {
+        Ok(())
+    }
+}
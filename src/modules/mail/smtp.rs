@@ -0,0 +1,232 @@
+use super::{Email, Mailer, build_message};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use std::str::FromStr;
+
+/// How the connection to the SMTP server is secured, read from `SMTP_TLS`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain text, only for a local SMTP sink (`none`).
+    None,
+    /// Upgrade a plain connection with STARTTLS (`starttls`).
+    StartTls,
+    /// TLS from the start (`tls`).
+    Tls,
+}
+
+impl SmtpTls {
+    /// Port used when `SMTP_PORT` is not set.
+    pub fn default_port(self) -> u16 {
+        match self {
+            SmtpTls::None => 25,
+            SmtpTls::StartTls => 587,
+            SmtpTls::Tls => 465,
+        }
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(SmtpTls::None),
+            "starttls" => Ok(SmtpTls::StartTls),
+            "tls" => Ok(SmtpTls::Tls),
+            _ => Err("must be none, starttls or tls".to_string()),
+        }
+    }
+}
+
+/// Connection settings, from `SMTP_HOST`, `SMTP_PORT`, `SMTP_USERNAME`,
+/// `SMTP_PASSWORD` and `SMTP_TLS`.
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    /// Username and password, when the server requires authentication.
+    pub credentials: Option<(String, String)>,
+}
+
+/// Sends mail through an SMTP server. Use `SMTP_TLS=none` to point it at a
+/// local SMTP sink such as Mailpit during development.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(settings: &SmtpSettings, from: Mailbox) -> Result<Self, String> {
+        let host = settings.host.as_str();
+
+        let builder = match settings.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .map_err(|e| format!("invalid SMTP relay: {}", e))?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .map_err(|e| format!("invalid SMTP relay: {}", e))?,
+        };
+
+        let mut builder = builder.port(settings.port);
+        if let Some((username, password)) = &settings.credentials {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), String> {
+        let message = build_message(&self.from, email)?;
+
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("SMTP error: {}", e))
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        match self.transport.test_connection().await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("SMTP server did not accept the connection".to_string()),
+            Err(e) => Err(format!("SMTP error: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// What the sink received for one message.
+    struct Received {
+        envelope: Vec<String>,
+        data: String,
+    }
+
+    /// Start a local SMTP sink that accepts one connection and reports every
+    /// message delivered over it. Returns the port it listens on.
+    fn smtp_sink() -> (u16, mpsc::Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut envelope = Vec::new();
+
+            write!(writer, "220 sink ESMTP\r\n").unwrap();
+
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                let command = line.trim_end().to_string();
+                line.clear();
+
+                let upper = command.to_ascii_uppercase();
+                if upper.starts_with("EHLO") || upper.starts_with("HELO") {
+                    write!(writer, "250 sink\r\n").unwrap();
+                } else if upper.starts_with("MAIL FROM") || upper.starts_with("RCPT TO") {
+                    envelope.push(command);
+                    write!(writer, "250 OK\r\n").unwrap();
+                } else if upper == "DATA" {
+                    write!(writer, "354 End data with <CR><LF>.<CR><LF>\r\n").unwrap();
+
+                    let mut data = String::new();
+                    while reader.read_line(&mut line).unwrap_or(0) > 0 && line != ".\r\n" {
+                        data.push_str(&line);
+                        line.clear();
+                    }
+                    line.clear();
+
+                    write!(writer, "250 OK\r\n").unwrap();
+                    let _ = sender.send(Received {
+                        envelope: std::mem::take(&mut envelope),
+                        data,
+                    });
+                } else if upper == "QUIT" {
+                    write!(writer, "221 Bye\r\n").unwrap();
+                    break;
+                } else {
+                    write!(writer, "250 OK\r\n").unwrap();
+                }
+            }
+        });
+
+        (port, receiver)
+    }
+
+    fn sink_settings(port: u16) -> SmtpSettings {
+        SmtpSettings {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::None,
+            credentials: None,
+        }
+    }
+
+    #[ntex::test]
+    async fn sends_to_smtp_sink() {
+        let (port, received) = smtp_sink();
+        let from: Mailbox = "rubete <no-reply@localhost>".parse().unwrap();
+        let mailer = SmtpMailer::new(&sink_settings(port), from).unwrap();
+
+        let email = Email {
+            to: "user@example.com".to_string(),
+            subject: "Reset your password".to_string(),
+            html_body: "<p>Open the reset link</p>".to_string(),
+            text_body: "Open the reset link".to_string(),
+        };
+        mailer.send(&email).await.unwrap();
+
+        let message = received.recv().unwrap();
+        assert_eq!(
+            message.envelope,
+            [
+                "MAIL FROM:<no-reply@localhost>",
+                "RCPT TO:<user@example.com>"
+            ]
+        );
+        assert!(message.data.contains("Subject: Reset your password\r\n"));
+        assert!(message.data.contains("To: user@example.com\r\n"));
+        assert!(message.data.contains("Content-Type: text/plain"));
+        assert!(message.data.contains("Content-Type: text/html"));
+        assert!(message.data.contains("Open the reset link"));
+    }
+
+    #[ntex::test]
+    async fn check_connects_to_smtp_sink() {
+        let (port, _received) = smtp_sink();
+        let from: Mailbox = "rubete <no-reply@localhost>".parse().unwrap();
+        let mailer = SmtpMailer::new(&sink_settings(port), from).unwrap();
+
+        assert!(mailer.check().await.is_ok());
+    }
+
+    #[ntex::test]
+    async fn check_fails_without_smtp_server() {
+        // Bind and drop a listener to get a port nothing listens on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let from: Mailbox = "rubete <no-reply@localhost>".parse().unwrap();
+        let mailer = SmtpMailer::new(&sink_settings(port), from).unwrap();
+
+        assert!(mailer.check().await.is_err());
+    }
+}
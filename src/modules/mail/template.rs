@@ -0,0 +1,58 @@
+use super::Email;
+
+/// Email templates shipped with the application. Placeholders are written as
+/// `{{ name }}` and replaced by `render`.
+#[derive(Clone, Copy)]
+pub enum Template {
+    ResetPassword,
+    VerifyEmail,
+}
+
+impl Template {
+    fn subject(self) -> &'static str {
+        match self {
+            Template::ResetPassword => "Reset your password",
+            Template::VerifyEmail => "Verify your email address",
+        }
+    }
+
+    fn html(self) -> &'static str {
+        match self {
+            Template::ResetPassword => include_str!("templates/reset_password.html"),
+            Template::VerifyEmail => include_str!("templates/verify_email.html"),
+        }
+    }
+
+    fn text(self) -> &'static str {
+        match self {
+            Template::ResetPassword => include_str!("templates/reset_password.txt"),
+            Template::VerifyEmail => include_str!("templates/verify_email.txt"),
+        }
+    }
+
+    /// Render both bodies of the template into an email for `to`.
+    pub fn render(self, to: &str, vars: &[(&str, &str)]) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: self.subject().to_string(),
+            html_body: render(self.html(), vars, escape_html),
+            text_body: render(self.text(), vars, str::to_string),
+        }
+    }
+}
+
+/// Replace every `{{ name }}` placeholder with its escaped value.
+fn render(source: &str, vars: &[(&str, &str)], escape: fn(&str) -> String) -> String {
+    vars.iter().fold(source.to_string(), |body, (name, value)| {
+        body.replace(&format!("{{{{ {} }}}}", name), &escape(value))
+    })
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,205 @@
+use crate::modules::database::entity::user_sessions::{self, Entity as UserSessionsEntity};
+use crate::modules::utils::token::TokenType;
+use chrono::Utc;
+use ntex::time::{Seconds, sleep};
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Buckets for database queries, which are mostly much faster than requests.
+const DB_QUERY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// How often `auth_active_sessions` is recounted.
+const SESSION_GAUGE_INTERVAL: Seconds = Seconds(30);
+
+/// Prometheus metrics of the process, served at `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    login_successes: IntCounter,
+    login_failures: IntCounterVec,
+    tokens_issued: IntCounterVec,
+    active_sessions: IntGauge,
+    db_query_duration: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    db_pool_max_connections: IntGauge,
+}
+
+/// Metrics shared by all workers.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric");
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests",
+            ),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric");
+        let login_successes =
+            IntCounter::new("auth_login_success_total", "Logins that started a session")
+                .expect("valid metric");
+        let login_failures = IntCounterVec::new(
+            Opts::new("auth_login_failures_total", "Refused login attempts"),
+            &["reason"],
+        )
+        .expect("valid metric");
+        let tokens_issued = IntCounterVec::new(
+            Opts::new("auth_tokens_issued_total", "Signed tokens issued"),
+            &["type"],
+        )
+        .expect("valid metric");
+        let active_sessions = IntGauge::new(
+            "auth_active_sessions",
+            "Sessions that are neither revoked nor expired",
+        )
+        .expect("valid metric");
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "db_query_duration_seconds",
+                "Time spent on database queries",
+            )
+            .buckets(DB_QUERY_BUCKETS.to_vec()),
+            &["operation", "failed"],
+        )
+        .expect("valid metric");
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Open database connections"),
+            &["state"],
+        )
+        .expect("valid metric");
+        let db_pool_max_connections = IntGauge::new(
+            "db_pool_max_connections",
+            "Maximum number of database connections",
+        )
+        .expect("valid metric");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(http_requests.clone()))
+            .and(registry.register(Box::new(http_request_duration.clone())))
+            .and(registry.register(Box::new(login_successes.clone())))
+            .and(registry.register(Box::new(login_failures.clone())))
+            .and(registry.register(Box::new(tokens_issued.clone())))
+            .and(registry.register(Box::new(active_sessions.clone())))
+            .and(registry.register(Box::new(db_query_duration.clone())))
+            .and(registry.register(Box::new(db_pool_connections.clone())))
+            .and(registry.register(Box::new(db_pool_max_connections.clone())))
+            .expect("metrics are registered once");
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            login_successes,
+            login_failures,
+            tokens_issued,
+            active_sessions,
+            db_query_duration,
+            db_pool_connections,
+            db_pool_max_connections,
+        }
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        let labels = [method, route, status.as_str()];
+
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_request_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn login_succeeded(&self) {
+        self.login_successes.inc();
+    }
+
+    /// Count a refused login, labelled with the error code sent to the client.
+    pub fn login_failed(&self, reason: &str) {
+        self.login_failures.with_label_values(&[reason]).inc();
+    }
+
+    pub fn token_issued(&self, typ: TokenType) {
+        self.tokens_issued.with_label_values(&[typ.as_str()]).inc();
+    }
+
+    pub fn observe_query(&self, sql: &str, failed: bool, elapsed: Duration) {
+        let operation = sql
+            .split_whitespace()
+            .next()
+            .map(str::to_ascii_lowercase)
+            .filter(|op| matches!(op.as_str(), "select" | "insert" | "update" | "delete"))
+            .unwrap_or_else(|| "other".to_string());
+
+        self.db_query_duration
+            .with_label_values(&[operation.as_str(), if failed { "true" } else { "false" }])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn set_active_sessions(&self, sessions: u64) {
+        self.active_sessions.set(sessions as i64);
+    }
+
+    /// Read the current size of the connection pool.
+    pub fn observe_pool(&self, db: &DatabaseConnection) {
+        let pool = db.get_mysql_connection_pool();
+        let idle = pool.num_idle() as i64;
+
+        self.db_pool_connections
+            .with_label_values(&["idle"])
+            .set(idle);
+        self.db_pool_connections
+            .with_label_values(&["in_use"])
+            .set(pool.size() as i64 - idle);
+        self.db_pool_max_connections
+            .set(pool.options().get_max_connections() as i64);
+    }
+
+    /// All metrics in the Prometheus text format.
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        TextEncoder::new().encode_to_string(&self.registry.gather())
+    }
+}
+
+/// Recount the active sessions every `SESSION_GAUGE_INTERVAL`, so scrapes of
+/// `/metrics` never query the database. Runs until the server stops.
+pub async fn refresh_session_gauge(db: DatabaseConnection) {
+    loop {
+        match UserSessionsEntity::find()
+            .filter(user_sessions::Column::RevokedAt.is_null())
+            .filter(user_sessions::Column::ExpiresAt.gt(Utc::now()))
+            .count(&db)
+            .await
+        {
+            Ok(sessions) => metrics().set_active_sessions(sessions),
+            Err(e) => tracing::warn!(error = %e, "Failed to count active sessions"),
+        }
+
+        sleep(SESSION_GAUGE_INTERVAL).await;
+    }
+}
+
+/// Time every query made through `db`. Call before the connection is shared.
+pub fn instrument_db(db: &mut DatabaseConnection) {
+    db.set_metric_callback(|info| {
+        metrics().observe_query(&info.statement.sql, info.failed, info.elapsed)
+    });
+}
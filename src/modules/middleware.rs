@@ -0,0 +1,4 @@
+pub mod access_log;
+pub mod auth;
+pub mod metrics;
+pub mod rate_limit;
@@ -0,0 +1,89 @@
+use crate::modules::utils::auth::AuthUser;
+use crate::modules::utils::request::{REQUEST_ID_HEADER, request_id, with_request_id};
+use ntex::http::header::{HeaderName, HeaderValue};
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::web::{DefaultError, WebRequest, WebResponse};
+use std::time::Instant;
+use tracing::Instrument;
+
+/// Middleware that assigns every request an ID and logs one line per request
+/// with the method, path, status, latency and authenticated user.
+///
+/// The ID is taken from the `X-Request-Id` header or generated, echoed in the
+/// response header and attached to every log line written during the request.
+/// Wrap it around the whole `App` so it sees every response.
+pub struct AccessLog;
+
+impl<S> Middleware<S> for AccessLog {
+    type Service = AccessLogMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        AccessLogMiddleware { service }
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<WebRequest<DefaultError>> for AccessLogMiddleware<S>
+where
+    S: Service<WebRequest<DefaultError>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<DefaultError>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let started = Instant::now();
+        let id = request_id(req.headers());
+        let method = req.method().clone();
+        let path = req.path().to_string();
+        let span = tracing::info_span!("request", request_id = %id);
+
+        let result = with_request_id(id.clone(), ctx.call(&self.service, req))
+            .instrument(span.clone())
+            .await;
+
+        let _entered = span.enter();
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        match result {
+            Ok(mut response) => {
+                let status = response.status().as_u16();
+                let user_id = response
+                    .request()
+                    .extensions()
+                    .get::<AuthUser>()
+                    .map(|user| user.user_id);
+
+                tracing::info!(
+                    method = %method,
+                    path = %path,
+                    status,
+                    latency_ms,
+                    user_id,
+                    "request completed"
+                );
+
+                if let Ok(value) = HeaderValue::from_str(&id) {
+                    response
+                        .headers_mut()
+                        .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                }
+
+                Ok(response)
+            }
+            Err(err) => {
+                tracing::error!(method = %method, path = %path, latency_ms, "request failed");
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::modules::config::AppConfig;
+use crate::modules::utils::auth::{AuthError, AuthUser, authenticate};
+use crate::modules::utils::rbac::has_permission;
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::web::{DefaultError, WebRequest, WebResponse};
+use sea_orm::DatabaseConnection;
+
+/// Middleware that rejects requests without a valid Bearer access token.
+///
+/// Protect a scope with `.wrap(RequireAuth)`; handlers inside it can take an
+/// `AuthUser` argument without validating the token a second time.
+pub struct RequireAuth;
+
+impl<S> Middleware<S> for RequireAuth {
+    type Service = RequireAuthMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        RequireAuthMiddleware { service }
+    }
+}
+
+pub struct RequireAuthMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<WebRequest<DefaultError>> for RequireAuthMiddleware<S>
+where
+    S: Service<WebRequest<DefaultError>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<DefaultError>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let config = req
+            .app_state::<AppConfig>()
+            .expect("AppConfig is not registered as app state");
+
+        match authenticate(req.headers(), config, req.app_state::<DatabaseConnection>()).await {
+            Ok(user) => {
+                req.extensions_mut().insert(user);
+                ctx.call(&self.service, req).await
+            }
+            Err(err) => Ok(req.error_response(err)),
+        }
+    }
+}
+
+/// Guard that only lets through users whose roles grant `permission`, for
+/// example `.wrap(require_permission("users:read"))`. Requests without a valid
+/// access token get a 401, users lacking the permission a 403.
+pub fn require_permission(permission: &'static str) -> RequirePermission {
+    RequirePermission { permission }
+}
+
+pub struct RequirePermission {
+    permission: &'static str,
+}
+
+impl<S> Middleware<S> for RequirePermission {
+    type Service = RequirePermissionMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        RequirePermissionMiddleware {
+            service,
+            permission: self.permission,
+        }
+    }
+}
+
+pub struct RequirePermissionMiddleware<S> {
+    service: S,
+    permission: &'static str,
+}
+
+impl<S> RequirePermissionMiddleware<S> {
+    async fn authorize(&self, req: &WebRequest<DefaultError>) -> Result<AuthUser, AuthError> {
+        let config = req
+            .app_state::<AppConfig>()
+            .expect("AppConfig is not registered as app state");
+        let db = req.app_state::<DatabaseConnection>();
+
+        // Reuse the user validated by an outer RequireAuth or guard, if any
+        let validated = req.extensions().get::<AuthUser>().cloned();
+        let user = match validated {
+            Some(user) => user,
+            None => authenticate(req.headers(), config, db).await?,
+        };
+
+        let db = db.ok_or(AuthError::Database)?;
+        match has_permission(db, &user.roles, self.permission).await {
+            Ok(true) => Ok(user),
+            Ok(false) => Err(AuthError::Forbidden),
+            Err(_) => Err(AuthError::Database),
+        }
+    }
+}
+
+impl<S> Service<WebRequest<DefaultError>> for RequirePermissionMiddleware<S>
+where
+    S: Service<WebRequest<DefaultError>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<DefaultError>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        match self.authorize(&req).await {
+            Ok(user) => {
+                req.extensions_mut().insert(user);
+                ctx.call(&self.service, req).await
+            }
+            Err(err) => Ok(req.error_response(err)),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::modules::metrics::metrics;
+use ntex::router::{Path, Router};
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::web::{DefaultError, WebRequest, WebResponse};
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Middleware that counts requests and their latency per method, route and status.
+///
+/// Routes are labelled with the pattern registered in `run_server`, such as
+/// `/v1/admin/users/{id}/suspend`, so ids in the path do not create new series.
+/// The path is matched against the route table before the request is handled,
+/// so responses of scope middleware such as `RequireAuth` or `RateLimit` get
+/// their route too. Paths of no route are labelled `unmatched`.
+/// Wrap it around the whole `App` so it sees every response.
+pub struct HttpMetrics {
+    routes: Rc<Router<&'static str>>,
+}
+
+impl HttpMetrics {
+    /// Label requests with `routes`, the full pattern of every registered route.
+    pub fn new(routes: &[&'static str]) -> Self {
+        let mut router = Router::build();
+        for route in routes {
+            router.path(*route, *route);
+        }
+
+        Self {
+            routes: Rc::new(router.finish()),
+        }
+    }
+}
+
+impl<S> Middleware<S> for HttpMetrics {
+    type Service = HttpMetricsMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        HttpMetricsMiddleware {
+            service,
+            routes: self.routes.clone(),
+        }
+    }
+}
+
+pub struct HttpMetricsMiddleware<S> {
+    service: S,
+    routes: Rc<Router<&'static str>>,
+}
+
+/// Pattern of the route `path` belongs to.
+fn route_label(routes: &Router<&'static str>, path: &str) -> &'static str {
+    routes
+        .recognize(&mut Path::new(path))
+        .map_or("unmatched", |(route, _)| *route)
+}
+
+impl<S> Service<WebRequest<DefaultError>> for HttpMetricsMiddleware<S>
+where
+    S: Service<WebRequest<DefaultError>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<DefaultError>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let started = Instant::now();
+        let route = route_label(&self.routes, req.path());
+        let response = ctx.call(&self.service, req).await?;
+
+        metrics().observe_request(
+            response.request().method().as_str(),
+            route,
+            response.status().as_u16(),
+            started.elapsed(),
+        );
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_paths_with_their_route_pattern() {
+        let metrics = HttpMetrics::new(&[
+            "/",
+            "/v1/me",
+            "/v1/admin/users/{id}",
+            "/v1/admin/users/{id}/suspend",
+        ]);
+        let routes = &metrics.routes;
+
+        assert_eq!(route_label(routes, "/"), "/");
+        assert_eq!(route_label(routes, "/v1/me"), "/v1/me");
+        assert_eq!(
+            route_label(routes, "/v1/admin/users/42"),
+            "/v1/admin/users/{id}"
+        );
+        assert_eq!(
+            route_label(routes, "/v1/admin/users/42/suspend"),
+            "/v1/admin/users/{id}/suspend"
+        );
+        assert_eq!(
+            route_label(routes, "/v1/admin/users/42/unknown"),
+            "unmatched"
+        );
+        assert_eq!(route_label(routes, "/wp-login.php"), "unmatched");
+    }
+}
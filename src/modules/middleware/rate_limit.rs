@@ -0,0 +1,443 @@
+use crate::modules::config::AppConfig;
+use crate::modules::utils::auth::bearer_token;
+use crate::modules::utils::request::client_ip;
+use crate::modules::utils::response::send_error;
+use crate::modules::utils::security::hash_token;
+use crate::modules::utils::token::{TokenType, decode_token};
+use async_trait::async_trait;
+use lru::LruCache;
+use ntex::http::Method;
+use ntex::http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::web::{DefaultError, WebRequest, WebResponse};
+use std::num::NonZeroUsize;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most buckets kept by the in-memory store.
+const MAX_BUCKETS: usize = 100_000;
+
+/// What a client is identified by when counting its requests.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKey {
+    /// Client IP address.
+    Ip,
+    /// `user_id` of a valid Bearer access token, falling back to the IP.
+    User,
+    /// API key from `RATE_LIMIT_API_KEYS` sent in the `X-API-Key` header,
+    /// falling back to the IP.
+    ApiKey,
+}
+
+impl FromStr for RateLimitKey {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ip" => Ok(RateLimitKey::Ip),
+            "user" => Ok(RateLimitKey::User),
+            "api_key" => Ok(RateLimitKey::ApiKey),
+            _ => Err("must be ip, user or api_key".to_string()),
+        }
+    }
+}
+
+/// A token bucket: `capacity` requests at once, refilled at `capacity` per `period`.
+#[derive(Clone)]
+pub struct RateLimitPolicy {
+    pub name: &'static str,
+    pub capacity: u32,
+    pub period: Duration,
+    pub key: RateLimitKey,
+}
+
+impl RateLimitPolicy {
+    pub fn new(name: &'static str, capacity: u32, period: Duration, key: RateLimitKey) -> Self {
+        Self {
+            name,
+            capacity: capacity.max(1),
+            period,
+            key,
+        }
+    }
+
+    /// Policy allowing `capacity` requests per minute.
+    pub fn per_minute(name: &'static str, capacity: u32, key: RateLimitKey) -> Self {
+        Self::new(name, capacity, Duration::from_secs(60), key)
+    }
+
+    fn refill_per_second(&self) -> f64 {
+        self.capacity as f64 / self.period.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Outcome of taking a token from a bucket.
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until a token is available (when denied) or the bucket is full again.
+    pub reset: u64,
+}
+
+/// Storage for token buckets. The in-memory store only limits a single process;
+/// a shared store (Redis, database) can implement this trait later.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn take(&self, key: &str, policy: &RateLimitPolicy) -> RateLimitDecision;
+}
+
+pub type SharedRateLimitStore = Arc<dyn RateLimitStore>;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Keeps token buckets in process memory, shared by all workers.
+///
+/// At most `MAX_BUCKETS` buckets are kept. A new client evicts the least
+/// recently used one, which has usually been idle long enough to be full again,
+/// so memory stays bounded and no request ever scans the whole store.
+pub struct InMemoryRateLimitStore {
+    buckets: Mutex<LruCache<String, Bucket>>,
+}
+
+impl Default for InMemoryRateLimitStore {
+    fn default() -> Self {
+        let capacity = NonZeroUsize::new(MAX_BUCKETS).expect("MAX_BUCKETS is not zero");
+
+        Self {
+            buckets: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn take(&self, key: &str, policy: &RateLimitPolicy) -> RateLimitDecision {
+        let now = Instant::now();
+        let capacity = policy.capacity as f64;
+        let rate = policy.refill_per_second();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        let bucket = buckets.get_or_insert_mut(key.to_string(), || Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+
+        // Refill for the time elapsed since the last request
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated_at = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let missing = if allowed {
+            capacity - bucket.tokens
+        } else {
+            1.0 - bucket.tokens
+        };
+
+        RateLimitDecision {
+            allowed,
+            limit: policy.capacity,
+            remaining: bucket.tokens.floor() as u32,
+            reset: (missing / rate).ceil() as u64,
+        }
+    }
+}
+
+struct RouteRule {
+    method: Option<Method>,
+    path: String,
+    policy: RateLimitPolicy,
+}
+
+impl RouteRule {
+    /// Whether `path` is the rule's path or below it. A plain prefix match would
+    /// also give `/v1/loginx` the limit of `/v1/login`.
+    fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(self.path.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+struct Inner {
+    store: SharedRateLimitStore,
+    default_policy: Option<RateLimitPolicy>,
+    routes: Vec<RouteRule>,
+}
+
+/// Token bucket rate limiting middleware.
+///
+/// Wrap a scope with it to apply `default_policy` to every request in that scope,
+/// and add `route` rules to give individual routes their own limits. Throttled
+/// clients get a 429 `ErrorResponse`; every limited response carries the
+/// `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers.
+pub struct RateLimit {
+    inner: Rc<Inner>,
+}
+
+impl RateLimit {
+    pub fn new(store: SharedRateLimitStore) -> Self {
+        Self {
+            inner: Rc::new(Inner {
+                store,
+                default_policy: None,
+                routes: Vec::new(),
+            }),
+        }
+    }
+
+    /// Policy for requests that match no route rule.
+    pub fn default_policy(mut self, policy: RateLimitPolicy) -> Self {
+        Rc::get_mut(&mut self.inner)
+            .expect("Multiple copies exist")
+            .default_policy = Some(policy);
+        self
+    }
+
+    /// Policy for requests to the full path `path` or any path below it, optionally
+    /// restricted to one method. The first matching rule wins.
+    pub fn route(mut self, method: Option<Method>, path: &str, policy: RateLimitPolicy) -> Self {
+        Rc::get_mut(&mut self.inner)
+            .expect("Multiple copies exist")
+            .routes
+            .push(RouteRule {
+                method,
+                path: path.to_string(),
+                policy,
+            });
+        self
+    }
+}
+
+impl<S> Middleware<S> for RateLimit {
+    type Service = RateLimitMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        RateLimitMiddleware {
+            service,
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    inner: Rc<Inner>,
+}
+
+impl Inner {
+    fn policy_for(&self, method: &Method, path: &str) -> Option<&RateLimitPolicy> {
+        self.routes
+            .iter()
+            .find(|rule| rule.method.as_ref().is_none_or(|m| m == method) && rule.matches(path))
+            .map(|rule| &rule.policy)
+            .or(self.default_policy.as_ref())
+    }
+}
+
+/// Identify the client according to the policy's key.
+fn client_key(req: &WebRequest<DefaultError>, key: RateLimitKey) -> String {
+    let config = req.app_state::<AppConfig>();
+    let trusted_proxies = config.map_or(0, |config| config.trusted_proxies);
+    let ip = || {
+        format!(
+            "ip:{}",
+            client_ip(req.peer_addr(), req.headers(), trusted_proxies)
+        )
+    };
+
+    match key {
+        RateLimitKey::Ip => ip(),
+        RateLimitKey::User => bearer_token(req.headers())
+            .zip(config)
+            .and_then(|(token, config)| decode_token(config, &token, TokenType::Access).ok())
+            .map(|claims| format!("user:{}", claims.user_id))
+            .unwrap_or_else(ip),
+        // Only configured keys get their own bucket, otherwise every made-up
+        // header value would be a fresh bucket
+        RateLimitKey::ApiKey => req
+            .headers()
+            .get("x-api-key")
+            .and_then(|value| value.to_str().ok())
+            .map(hash_token)
+            .filter(|hash| config.is_some_and(|config| config.rate_limits.api_keys.contains(hash)))
+            .map(|hash| format!("api_key:{}", hash))
+            .unwrap_or_else(ip),
+    }
+}
+
+fn insert_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    headers.insert(
+        HeaderName::from_static("ratelimit-limit"),
+        HeaderValue::from(decision.limit),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-remaining"),
+        HeaderValue::from(decision.remaining),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-reset"),
+        HeaderValue::from(decision.reset),
+    );
+}
+
+impl<S> Service<WebRequest<DefaultError>> for RateLimitMiddleware<S>
+where
+    S: Service<WebRequest<DefaultError>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<DefaultError>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let policy = match self.inner.policy_for(req.method(), req.path()) {
+            Some(policy) => policy,
+            None => return ctx.call(&self.service, req).await,
+        };
+
+        let key = format!("{}:{}", policy.name, client_key(&req, policy.key));
+        let decision = self.inner.store.take(&key, policy).await;
+
+        if !decision.allowed {
+            let mut response = send_error(
+                429,
+                "rate_limited",
+                "Too many requests, try again later",
+                Option::<()>::None,
+            );
+            insert_headers(response.headers_mut(), &decision);
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(decision.reset.max(1)));
+            return Ok(req.into_response(response));
+        }
+
+        let mut response = ctx.call(&self.service, req).await?;
+        insert_headers(response.headers_mut(), &decision);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntex::web::{self, App, test};
+
+    fn policy(capacity: u32) -> RateLimitPolicy {
+        RateLimitPolicy::per_minute("test", capacity, RateLimitKey::Ip)
+    }
+
+    #[ntex::test]
+    async fn bucket_is_exhausted_after_capacity() {
+        let store = InMemoryRateLimitStore::default();
+        let policy = policy(2);
+
+        let first = store.take("client", &policy).await;
+        assert!(first.allowed);
+        assert_eq!((first.limit, first.remaining), (2, 1));
+
+        let second = store.take("client", &policy).await;
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+        // Two tokens are missing at one every 30 seconds
+        assert_eq!(second.reset, 60);
+
+        let third = store.take("client", &policy).await;
+        assert!(!third.allowed);
+        assert_eq!(third.remaining, 0);
+        assert_eq!(third.reset, 30);
+
+        assert!(store.take("other", &policy).await.allowed);
+    }
+
+    #[ntex::test]
+    async fn bucket_refills_over_time() {
+        let store = InMemoryRateLimitStore::default();
+        let policy = policy(2);
+
+        store.take("client", &policy).await;
+        store.take("client", &policy).await;
+        assert!(!store.take("client", &policy).await.allowed);
+
+        // Pretend the last request was 30 seconds ago, enough for one token
+        {
+            let mut buckets = store.buckets.lock().unwrap();
+            let bucket = buckets.get_mut("client").unwrap();
+            bucket.updated_at = bucket
+                .updated_at
+                .checked_sub(Duration::from_secs(30))
+                .unwrap();
+        }
+
+        let decision = store.take("client", &policy).await;
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert!(!store.take("client", &policy).await.allowed);
+    }
+
+    #[test]
+    fn route_rules_match_on_path_segments() {
+        let limit = RateLimit::new(Arc::new(InMemoryRateLimitStore::default()))
+            .default_policy(RateLimitPolicy::per_minute("default", 1, RateLimitKey::Ip))
+            .route(
+                Some(Method::POST),
+                "/v1/login",
+                RateLimitPolicy::per_minute("login", 1, RateLimitKey::Ip),
+            )
+            .route(
+                None,
+                "/v1/password",
+                RateLimitPolicy::per_minute("password", 1, RateLimitKey::Ip),
+            );
+        let name = |method: Method, path: &str| {
+            limit
+                .inner
+                .policy_for(&method, path)
+                .map(|policy| policy.name)
+        };
+
+        assert_eq!(name(Method::POST, "/v1/login"), Some("login"));
+        assert_eq!(name(Method::POST, "/v1/login/"), Some("login"));
+        assert_eq!(name(Method::GET, "/v1/login"), Some("default"));
+        assert_eq!(name(Method::POST, "/v1/loginx"), Some("default"));
+        assert_eq!(name(Method::GET, "/v1/password/reset"), Some("password"));
+        assert_eq!(name(Method::POST, "/v1/passwords"), Some("default"));
+    }
+
+    #[ntex::test]
+    async fn throttled_requests_get_retry_after() {
+        let app = test::init_service(
+            App::new()
+                .wrap(
+                    RateLimit::new(Arc::new(InMemoryRateLimitStore::default()))
+                        .default_policy(policy(1)),
+                )
+                .route("/", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        let response = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers().get("ratelimit-remaining").unwrap(), "0");
+
+        let response = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(response.status().as_u16(), 429);
+        assert_eq!(response.headers().get("ratelimit-reset").unwrap(), "60");
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "60");
+    }
+}
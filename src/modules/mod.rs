@@ -1,4 +1,10 @@
+pub mod config;
 pub mod database;
 pub mod handlers;
+pub mod logging;
+pub mod mail;
+pub mod metrics;
+pub mod middleware;
 pub mod routes;
+pub mod shutdown;
 pub mod utils;
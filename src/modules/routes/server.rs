@@ -1,28 +1,305 @@
+use crate::modules::config::{AppConfig, RateLimits};
 use crate::modules::handlers::{
-    health_check::health_check, home::home, module::users::create::create_user,
-    module::users::login::login_user,
+    health_check::health_check, health_check::livez, health_check::readyz, home::home, jwks::jwks,
+    metrics::prometheus_metrics, module::admin::roles::list_roles,
+    module::admin::users::actions::delete_user, module::admin::users::actions::force_logout_user,
+    module::admin::users::actions::restore_user, module::admin::users::actions::suspend_user,
+    module::admin::users::actions::unsuspend_user, module::admin::users::list::list_users,
+    module::admin::users::show::get_user, module::email::verify::resend_verification_email,
+    module::email::verify::verify_email, module::me::mfa::confirm_totp,
+    module::me::mfa::disable_totp, module::me::mfa::enroll_totp,
+    module::me::password::change_password, module::me::profile::get_profile,
+    module::me::profile::update_profile, module::password::forgot::forgot_password,
+    module::password::reset::reset_password, module::users::create::create_user,
+    module::users::login::login_user, module::users::login_mfa::login_mfa,
+    module::users::logout::logout_all, module::users::logout::logout_user,
+    module::users::refresh::refresh_token,
 };
+use crate::modules::mail::SharedMailer;
+use crate::modules::metrics::refresh_session_gauge;
+use crate::modules::middleware::access_log::AccessLog;
+use crate::modules::middleware::auth::{RequireAuth, require_permission};
+use crate::modules::middleware::metrics::HttpMetrics;
+use crate::modules::middleware::rate_limit::{
+    InMemoryRateLimitStore, RateLimit, RateLimitKey, RateLimitPolicy, SharedRateLimitStore,
+};
+use crate::modules::shutdown::stop_on_signal;
+use crate::modules::utils::lockout::LoginThrottle;
+use ntex::http::Method;
+use ntex::time::Seconds;
 use ntex::web;
 use ntex::web::{App, HttpServer};
 use sea_orm::DbConn;
+use std::sync::Arc;
+
+/// Full pattern of every route registered in `routes`, used as the `route`
+/// label of the HTTP metrics. Add new routes here as well, a test checks that
+/// none is missing.
+const ROUTES: &[&str] = &[
+    "/",
+    "/healthz",
+    "/livez",
+    "/readyz",
+    "/.well-known/jwks.json",
+    "/metrics",
+    "/v1/",
+    "/v1/users",
+    "/v1/login",
+    "/v1/login/mfa",
+    "/v1/logout",
+    "/v1/logout/all",
+    "/v1/token/refresh",
+    "/v1/password/forgot",
+    "/v1/password/reset",
+    "/v1/email/verify",
+    "/v1/email/verify/resend",
+    "/v1/me",
+    "/v1/me/password",
+    "/v1/me/mfa/totp",
+    "/v1/me/mfa/totp/confirm",
+    "/v1/me/mfa/totp/disable",
+    "/v1/admin/roles",
+    "/v1/admin/users",
+    "/v1/admin/users/{id}",
+    "/v1/admin/users/{id}/suspend",
+    "/v1/admin/users/{id}/unsuspend",
+    "/v1/admin/users/{id}/restore",
+    "/v1/admin/users/{id}/logout",
+];
+
+/// Rate limits for the /v1 scope. Anonymous routes are limited per IP, routes
+/// of the logged-in user per user. Limits are requests per minute.
+fn v1_rate_limit(store: SharedRateLimitStore, limits: &RateLimits) -> RateLimit {
+    use RateLimitPolicy as Policy;
+
+    RateLimit::new(store)
+        .route(
+            Some(Method::POST),
+            "/v1/login",
+            Policy::per_minute("login", limits.login, RateLimitKey::Ip),
+        )
+        .route(
+            Some(Method::POST),
+            "/v1/users",
+            Policy::per_minute("register", limits.register, RateLimitKey::Ip),
+        )
+        .route(
+            Some(Method::POST),
+            "/v1/password",
+            Policy::per_minute("password", limits.password, RateLimitKey::Ip),
+        )
+        .route(
+            None,
+            "/v1/me",
+            Policy::per_minute("me", limits.user, RateLimitKey::User),
+        )
+        .default_policy(Policy::per_minute(
+            "default",
+            limits.default,
+            limits.default_key,
+        ))
+}
+
+/// Register every route. Their full patterns must be listed in `ROUTES`.
+fn routes(cfg: &mut web::ServiceConfig, v1_rate_limit: RateLimit) {
+    // Root routes
+    cfg.service(home)
+        .service(health_check)
+        .service(livez)
+        .service(readyz)
+        .service(jwks)
+        .service(prometheus_metrics)
+        // Define /v1 scope
+        .service(
+            web::scope("/v1")
+                .wrap(v1_rate_limit)
+                .service(create_user)
+                .service(home)
+                .service(login_user)
+                .service(login_mfa)
+                .service(
+                    web::scope("/logout")
+                        .wrap(RequireAuth)
+                        .service(logout_user)
+                        .service(logout_all),
+                )
+                .service(refresh_token)
+                .service(forgot_password)
+                .service(reset_password)
+                .service(verify_email)
+                .service(resend_verification_email)
+                .service(
+                    web::scope("/me")
+                        .wrap(RequireAuth)
+                        .service(get_profile)
+                        .service(update_profile)
+                        .service(change_password)
+                        .service(enroll_totp)
+                        .service(confirm_totp)
+                        .service(disable_totp),
+                )
+                .service(
+                    web::scope("/admin")
+                        .wrap(RequireAuth)
+                        .service(
+                            web::scope("/roles")
+                                .wrap(require_permission("roles:read"))
+                                .service(list_roles),
+                        )
+                        .service(
+                            web::scope("/users")
+                                .wrap(require_permission("users:read"))
+                                .service(list_users)
+                                .service(get_user)
+                                // Changes need users:write on top of users:read
+                                .service(
+                                    web::scope("/{id}")
+                                        .wrap(require_permission("users:write"))
+                                        .service(suspend_user)
+                                        .service(unsuspend_user)
+                                        .service(delete_user)
+                                        .service(restore_user)
+                                        .service(force_logout_user),
+                                ),
+                        ),
+                ),
+        );
+}
+
+pub async fn run_server(
+    config: AppConfig,
+    db: DbConn,
+    mailer: SharedMailer,
+) -> std::io::Result<()> {
+    let app_port = config.app_port;
+    let drain_delay = Seconds(config.shutdown_drain_delay_seconds);
+    let grace_period = Seconds(config.shutdown_grace_period_seconds);
+
+    // Failed login tracking is shared by all workers
+    let login_throttle = Arc::new(LoginThrottle::new(config.lockout.clone()));
+
+    // Rate limit buckets are shared by all workers
+    let rate_limit_store: SharedRateLimitStore = Arc::new(InMemoryRateLimitStore::default());
+
+    // Keep the active session gauge current while metrics are served
+    if config.metrics_token_hash.is_some() {
+        ntex::rt::spawn(refresh_session_gauge(db.clone()));
+    }
 
-pub async fn run_server(app_port: u16, db: DbConn) -> std::io::Result<()> {
-    HttpServer::new(move || {
+    tracing::info!(
+        port = app_port,
+        version = %config.app_version,
+        "Starting server"
+    );
+
+    let server = HttpServer::new(move || {
         App::new()
+            // Count and time every response
+            .wrap(HttpMetrics::new(ROUTES))
+            // Request IDs and the access log cover every response
+            .wrap(AccessLog)
+            // Add the validated configuration to app state
+            .state(config.clone())
             // Add DbConn to app state
             .state(db.clone()) // Add DbConn to app state
-            // Root routes
-            .service(home)
-            .service(health_check)
-            // Define /v1 scope
-            .service(
-                web::scope("/v1")
-                    .service(create_user)
-                    .service(home)
-                    .service(login_user),
-            )
+            // Add the configured mail transport to app state
+            .state(mailer.clone())
+            // Add failed login tracking to app state
+            .state(login_throttle.clone())
+            .configure(|cfg| {
+                routes(
+                    cfg,
+                    v1_rate_limit(rate_limit_store.clone(), &config.rate_limits),
+                )
+            })
     })
     .bind(("0.0.0.0", app_port))?
-    .run()
-    .await
+    // Signals are handled by stop_on_signal, which fails /readyz before stopping
+    .disable_signals()
+    .shutdown_timeout(grace_period)
+    .run();
+
+    ntex::rt::spawn(stop_on_signal(server.clone(), drain_delay));
+
+    server.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntex::web::error::UrlGenerationError;
+    use ntex::web::{HttpRequest, HttpResponse, test};
+    use std::fs;
+    use std::path::Path;
+
+    /// Names of the handlers declared with a route attribute under `dir`, which
+    /// ntex also uses as the names of their resources.
+    fn handler_names(dir: &Path, names: &mut Vec<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                handler_names(&path, names);
+                continue;
+            }
+
+            let source = fs::read_to_string(&path).unwrap();
+            let mut routed = false;
+            for line in source.lines().map(str::trim) {
+                if line.starts_with("#[web::") {
+                    routed = true;
+                } else if let Some(rest) = line.strip_prefix("pub async fn ").filter(|_| routed) {
+                    names.push(rest.split('(').next().unwrap().to_string());
+                    routed = false;
+                }
+            }
+        }
+    }
+
+    #[ntex::test]
+    async fn every_registered_route_is_in_the_route_table() {
+        let mut names = Vec::new();
+        handler_names(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("src/modules/handlers"),
+            &mut names,
+        );
+        assert!(names.iter().any(|name| name == "login_user"), "{:?}", names);
+
+        // Resolve the full pattern of every handler from the real route tree
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| {
+                    routes(
+                        cfg,
+                        RateLimit::new(Arc::new(InMemoryRateLimitStore::default())),
+                    )
+                })
+                .default_service(web::to(move |req: HttpRequest| {
+                    let patterns: Vec<String> = names
+                        .iter()
+                        .filter_map(|name| match req.url_for(name, ["{id}"]) {
+                            Ok(url) => Some(url.path().replace("%7B", "{").replace("%7D", "}")),
+                            Err(UrlGenerationError::ResourceNotFound) => {
+                                Some(format!("{} is not registered, it", name))
+                            }
+                            // Resources at the root of their scope, like delete_user,
+                            // have no URL of their own; the scope is a route anyway
+                            Err(_) => None,
+                        })
+                        .collect();
+                    async move { HttpResponse::Ok().json(&patterns) }
+                })),
+        )
+        .await;
+
+        let request = test::TestRequest::get().uri("/__routes").to_request();
+        let patterns: Vec<String> = test::read_response_json(&app, request).await;
+
+        for pattern in patterns {
+            assert!(
+                ROUTES.contains(&pattern.as_str()),
+                "{} is missing from ROUTES",
+                pattern
+            );
+        }
+    }
 }
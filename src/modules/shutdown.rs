@@ -0,0 +1,71 @@
+use crate::modules::mail::wait_for_queued_emails;
+use ntex::server::Server;
+use ntex::time::{Seconds, sleep};
+use sea_orm::DatabaseConnection;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once a shutdown signal arrived, `/readyz` fails from then on.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+/// Wait for SIGTERM or SIGINT (Ctrl-C) and return its name.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use ntex::util::{Either, select};
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut term = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        let mut int = signal(SignalKind::interrupt()).expect("Failed to listen for SIGINT");
+
+        match select(term.recv(), int.recv()).await {
+            Either::Left(_) => "SIGTERM",
+            Either::Right(_) => "SIGINT",
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }
+}
+
+/// Stop `server` gracefully on SIGTERM or SIGINT.
+///
+/// `/readyz` starts failing at once, while new connections are still accepted
+/// for `drain_delay` so load balancers have time to notice. Then the listener is
+/// closed and in-flight requests get the grace period set on the server.
+pub async fn stop_on_signal(server: Server, drain_delay: Seconds) {
+    let signal = shutdown_signal().await;
+    SHUTTING_DOWN.store(true, Ordering::Relaxed);
+
+    tracing::info!(
+        signal,
+        drain_delay_seconds = drain_delay.0,
+        "Shutting down, no longer ready"
+    );
+    sleep(drain_delay).await;
+
+    tracing::info!("Closing listeners and draining in-flight requests");
+    server.stop(true).await;
+}
+
+/// Clean up after the server stopped: deliver queued emails within
+/// `grace_period`, close the database pool and flush the logs.
+pub async fn finish(db: DatabaseConnection, grace_period: Seconds) {
+    if !wait_for_queued_emails(grace_period).await {
+        tracing::warn!("Queued emails not sent before the grace period ended");
+    }
+
+    if let Err(e) = db.close().await {
+        tracing::error!(error = %e, "Failed to close the database pool");
+    }
+
+    tracing::info!("Shutdown complete");
+    let _ = std::io::stdout().flush();
+}
@@ -1,3 +1,12 @@
-pub mod json;
+pub mod auth;
+pub mod cookie;
+pub mod error;
+pub mod jwt_keys;
+pub mod lockout;
+pub mod mfa;
+pub mod password_policy;
+pub mod rbac;
+pub mod request;
 pub mod response;
 pub mod security;
+pub mod token;
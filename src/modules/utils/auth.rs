@@ -0,0 +1,152 @@
+use crate::modules::config::AppConfig;
+use crate::modules::database::entity::user_sessions::{self, Entity as UserSessionsEntity};
+use crate::modules::database::entity::users::Entity as UsersEntity;
+use crate::modules::utils::response::send_error;
+use crate::modules::utils::token::{TokenType, decode_token};
+use chrono::Utc;
+use ntex::http::{HeaderMap, Payload, StatusCode, header};
+use ntex::web::{DefaultError, FromRequest, HttpRequest, HttpResponse, WebResponseError};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use std::fmt;
+
+/// The user behind a validated access token.
+///
+/// Use it as a handler argument to require a logged-in user. When the route is
+/// wrapped in `RequireAuth`, the already validated user is reused.
+#[derive(Clone, Debug)]
+pub struct AuthUser {
+    pub user_id: i32,
+    /// Session family of the token (`user_sessions.family_id`).
+    pub sid: String,
+    /// Roles embedded in the token.
+    pub roles: Vec<String>,
+}
+
+/// Reasons a request could not be authenticated.
+#[derive(Debug)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+    SessionRevoked,
+    AccountDisabled,
+    Forbidden,
+    Database,
+}
+
+impl AuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::MissingToken => "missing_token",
+            AuthError::InvalidToken => "invalid_token",
+            AuthError::SessionRevoked => "session_revoked",
+            AuthError::AccountDisabled => "account_disabled",
+            AuthError::Forbidden => "forbidden",
+            AuthError::Database => "db_error",
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            AuthError::MissingToken => "Access token is missing",
+            AuthError::InvalidToken => "Invalid or expired token",
+            AuthError::SessionRevoked => "Session has been revoked",
+            AuthError::AccountDisabled => "Account is disabled",
+            AuthError::Forbidden => "You do not have permission to perform this action",
+            AuthError::Database => "Database error",
+        };
+        f.write_str(message)
+    }
+}
+
+impl WebResponseError<DefaultError> for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::MissingToken | AuthError::InvalidToken | AuthError::SessionRevoked => {
+                StatusCode::UNAUTHORIZED
+            }
+            AuthError::AccountDisabled | AuthError::Forbidden => StatusCode::FORBIDDEN,
+            AuthError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self, _: &HttpRequest) -> HttpResponse {
+        send_error(
+            self.status_code().as_u16(),
+            self.code(),
+            self.to_string(),
+            Option::<()>::None,
+        )
+    }
+}
+
+/// Extract the token from an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// Check that the session family an access token belongs to is still active and
+/// that its user has not been disabled.
+async fn check_session(db: &DatabaseConnection, sid: &str) -> Result<(), AuthError> {
+    let session = UserSessionsEntity::find()
+        .filter(user_sessions::Column::FamilyId.eq(sid))
+        .filter(user_sessions::Column::RevokedAt.is_null())
+        .filter(user_sessions::Column::ExpiresAt.gt(Utc::now()))
+        .find_also_related(UsersEntity)
+        .one(db)
+        .await
+        .map_err(|_| AuthError::Database)?;
+
+    match session {
+        None => Err(AuthError::SessionRevoked),
+        Some((_, Some(user))) if user.deleted_at.is_none() && user.suspended_at.is_none() => Ok(()),
+        Some(_) => Err(AuthError::AccountDisabled),
+    }
+}
+
+/// Validate the Bearer access token found in the request headers.
+pub async fn authenticate(
+    headers: &HeaderMap,
+    config: &AppConfig,
+    db: Option<&DatabaseConnection>,
+) -> Result<AuthUser, AuthError> {
+    let token = bearer_token(headers).ok_or(AuthError::MissingToken)?;
+
+    let claims =
+        decode_token(config, &token, TokenType::Access).map_err(|_| AuthError::InvalidToken)?;
+
+    // Revoked sessions must stop working right away, not only once the token expires
+    if config.is_stateful_session_mode() {
+        check_session(db.ok_or(AuthError::Database)?, &claims.sid).await?;
+    }
+
+    Ok(AuthUser {
+        user_id: claims.user_id,
+        sid: claims.sid,
+        roles: claims.roles,
+    })
+}
+
+impl FromRequest<DefaultError> for AuthUser {
+    type Error = AuthError;
+
+    async fn from_request(req: &HttpRequest, _: &mut Payload) -> Result<Self, Self::Error> {
+        // Reuse the user validated by the RequireAuth middleware, if any
+        let validated = req.extensions().get::<AuthUser>().cloned();
+        if let Some(user) = validated {
+            return Ok(user);
+        }
+
+        let config = req
+            .app_state::<AppConfig>()
+            .expect("AppConfig is not registered as app state");
+
+        authenticate(req.headers(), config, req.app_state::<DatabaseConnection>()).await
+    }
+}
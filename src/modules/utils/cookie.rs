@@ -0,0 +1,30 @@
+use cookie::time::Duration;
+use cookie::{Cookie, SameSite};
+
+/// Name of the cookie carrying the refresh token.
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+
+/// Path the refresh token cookie is scoped to, so it is only sent to the API.
+const REFRESH_TOKEN_COOKIE_PATH: &str = "/v1";
+
+/// Build the HttpOnly, Secure, SameSite=Strict cookie that carries the refresh token.
+pub fn refresh_token_cookie(token: &str, max_age: chrono::Duration) -> Cookie<'static> {
+    Cookie::build((REFRESH_TOKEN_COOKIE, token.to_string()))
+        .path(REFRESH_TOKEN_COOKIE_PATH)
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::seconds(max_age.num_seconds()))
+        .build()
+}
+
+/// Build an expired refresh token cookie that makes the browser drop the stored one.
+pub fn clear_refresh_token_cookie() -> Cookie<'static> {
+    Cookie::build((REFRESH_TOKEN_COOKIE, ""))
+        .path(REFRESH_TOKEN_COOKIE_PATH)
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::ZERO)
+        .build()
+}
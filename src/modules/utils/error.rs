@@ -0,0 +1,271 @@
+use crate::modules::utils::auth::AuthError;
+use crate::modules::utils::response::{send_error, with_retry_after};
+use crate::modules::utils::security::PasswordError;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind as JwtErrorKind};
+use ntex::http::StatusCode;
+use ntex::web::error::JsonPayloadError;
+use ntex::web::{DefaultError, HttpRequest, HttpResponse, WebResponseError};
+use sea_orm::DbErr;
+use std::fmt;
+use validator::ValidationErrors;
+
+/// Error returned by handlers, rendered as an `ErrorResponse`.
+///
+/// Handlers return `Result<HttpResponse, AppError>` and use `?`. The cause of
+/// database and internal errors is logged and never sent to the client.
+#[derive(Debug)]
+pub enum AppError {
+    /// A database query failed.
+    Database(DbErr),
+    /// The request body is not valid JSON for the endpoint.
+    InvalidPayload(String),
+    /// The request body failed validation.
+    Validation(ValidationErrors),
+    /// A token could not be signed, or was rejected.
+    Token(JwtError),
+    /// The request could not be authenticated or authorized.
+    Auth(AuthError),
+    /// A password could not be hashed or verified.
+    Password(PasswordError),
+    /// The request cannot be processed as sent (400).
+    BadRequest { code: &'static str, message: String },
+    /// Credentials or a token were rejected (401).
+    Unauthorized { code: &'static str, message: String },
+    /// The user may not do this (403).
+    Forbidden { code: &'static str, message: String },
+    /// The requested record does not exist (404).
+    NotFound { code: &'static str, message: String },
+    /// The request conflicts with the current state (409).
+    Conflict { code: &'static str, message: String },
+    /// The account is locked after too many failed logins (423).
+    Locked { until: DateTime<Utc> },
+    /// The client failed too often and has to wait (429).
+    TooManyAttempts { retry_after: i64 },
+    /// Anything else that went wrong on the server (500). Only `message` is
+    /// sent to the client, `detail` is logged.
+    Internal {
+        code: &'static str,
+        message: String,
+        detail: String,
+    },
+}
+
+impl AppError {
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        AppError::BadRequest {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn unauthorized(code: &'static str, message: impl Into<String>) -> Self {
+        AppError::Unauthorized {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn forbidden(code: &'static str, message: impl Into<String>) -> Self {
+        AppError::Forbidden {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        AppError::NotFound {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn conflict(code: &'static str, message: impl Into<String>) -> Self {
+        AppError::Conflict {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn internal(
+        code: &'static str,
+        message: impl Into<String>,
+        detail: impl fmt::Display,
+    ) -> Self {
+        AppError::Internal {
+            code,
+            message: message.into(),
+            detail: detail.to_string(),
+        }
+    }
+
+    /// Whether a token error comes from our own keys rather than from the token.
+    fn is_signing_error(err: &JwtError) -> bool {
+        matches!(
+            err.kind(),
+            JwtErrorKind::InvalidEcdsaKey
+                | JwtErrorKind::InvalidRsaKey(_)
+                | JwtErrorKind::RsaFailedSigning
+                | JwtErrorKind::InvalidKeyFormat
+                | JwtErrorKind::Crypto(_)
+        )
+    }
+
+    /// Machine-readable code sent to the client.
+    pub fn code(&self) -> &str {
+        match self {
+            AppError::Database(_) => "db_error",
+            AppError::InvalidPayload(_) => "invalid_payload",
+            AppError::Validation(_) => "validation_error",
+            AppError::Token(err) if Self::is_signing_error(err) => "token_error",
+            AppError::Token(_) => "invalid_token",
+            AppError::Auth(err) => err.code(),
+            AppError::Password(PasswordError::Busy) => "service_busy",
+            AppError::Password(PasswordError::Failed) => "hash_error",
+            AppError::Locked { .. } => "account_locked",
+            AppError::TooManyAttempts { .. } => "too_many_attempts",
+            AppError::BadRequest { code, .. }
+            | AppError::Unauthorized { code, .. }
+            | AppError::Forbidden { code, .. }
+            | AppError::NotFound { code, .. }
+            | AppError::Conflict { code, .. }
+            | AppError::Internal { code, .. } => code,
+        }
+    }
+
+    /// Cause worth logging, for errors whose details are not sent to the client.
+    fn log_detail(&self) -> Option<String> {
+        match self {
+            AppError::Database(err) => Some(err.to_string()),
+            AppError::Token(err) if Self::is_signing_error(err) => Some(err.to_string()),
+            AppError::Internal { detail, .. } => Some(detail.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Database(_) => f.write_str("Database error"),
+            AppError::InvalidPayload(message) => f.write_str(message),
+            AppError::Validation(_) => f.write_str("Validation failed"),
+            AppError::Token(err) if Self::is_signing_error(err) => {
+                f.write_str("Failed to generate token")
+            }
+            AppError::Token(_) => f.write_str("Invalid or expired token"),
+            AppError::Auth(err) => err.fmt(f),
+            AppError::Password(PasswordError::Busy) => {
+                f.write_str("Server is busy, please try again shortly")
+            }
+            AppError::Password(PasswordError::Failed) => f.write_str("Failed to process password"),
+            AppError::Locked { .. } => {
+                f.write_str("Account is temporarily locked after too many failed login attempts")
+            }
+            AppError::TooManyAttempts { .. } => {
+                f.write_str("Too many failed login attempts, try again later")
+            }
+            AppError::BadRequest { message, .. }
+            | AppError::Unauthorized { message, .. }
+            | AppError::Forbidden { message, .. }
+            | AppError::NotFound { message, .. }
+            | AppError::Conflict { message, .. }
+            | AppError::Internal { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl WebResponseError<DefaultError> for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Database(_) | AppError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::InvalidPayload(_) | AppError::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Token(err) if Self::is_signing_error(err) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AppError::Token(_) | AppError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            AppError::Auth(err) => err.status_code(),
+            AppError::Password(PasswordError::Busy) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Password(PasswordError::Failed) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            AppError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::Locked { .. } => StatusCode::LOCKED,
+            AppError::TooManyAttempts { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn error_response(&self, req: &HttpRequest) -> HttpResponse {
+        if let Some(detail) = self.log_detail() {
+            tracing::error!(
+                method = %req.method(),
+                path = req.path(),
+                code = self.code(),
+                error = %detail,
+                "request failed"
+            );
+        }
+
+        let status = self.status_code().as_u16();
+        match self {
+            AppError::Validation(errors) => {
+                send_error(status, self.code(), self.to_string(), Some(errors))
+            }
+            AppError::Locked { until } => with_retry_after(
+                send_error(
+                    status,
+                    self.code(),
+                    self.to_string(),
+                    Some(serde_json::json!({ "locked_until": until })),
+                ),
+                (*until - Utc::now()).num_seconds(),
+            ),
+            AppError::TooManyAttempts { retry_after } => with_retry_after(
+                send_error(status, self.code(), self.to_string(), Option::<()>::None),
+                *retry_after,
+            ),
+            AppError::Password(PasswordError::Busy) => with_retry_after(
+                send_error(status, self.code(), self.to_string(), Option::<()>::None),
+                1,
+            ),
+            _ => send_error(status, self.code(), self.to_string(), Option::<()>::None),
+        }
+    }
+}
+
+impl From<DbErr> for AppError {
+    fn from(err: DbErr) -> Self {
+        AppError::Database(err)
+    }
+}
+
+impl From<JsonPayloadError> for AppError {
+    fn from(err: JsonPayloadError) -> Self {
+        AppError::InvalidPayload(err.to_string())
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        AppError::Validation(errors)
+    }
+}
+
+impl From<JwtError> for AppError {
+    fn from(err: JwtError) -> Self {
+        AppError::Token(err)
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(err: AuthError) -> Self {
+        AppError::Auth(err)
+    }
+}
+
+impl From<PasswordError> for AppError {
+    fn from(err: PasswordError) -> Self {
+        AppError::Password(err)
+    }
+}
@@ -0,0 +1,297 @@
+use crate::modules::database::entity::activities::ActiveModel as ActivitiesActiveModel;
+use crate::modules::database::entity::users::{self, Entity as UsersEntity};
+use chrono::{DateTime, Duration, Utc};
+use lru::LruCache;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde_json::json;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+/// Most client IPs tracked at once.
+const MAX_TRACKED_IPS: usize = 10_000;
+
+/// Lockout settings, part of `AppConfig`.
+#[derive(Clone)]
+pub struct LockoutPolicy {
+    /// Failed logins on one account before it is locked (`LOGIN_MAX_FAILED_ATTEMPTS`).
+    pub max_attempts: i32,
+    /// First lockout period; it doubles with every further lockout (`LOGIN_LOCKOUT_MINUTES`).
+    pub lockout: Duration,
+    /// Upper bound for lockouts and IP delays (`LOGIN_MAX_LOCKOUT_MINUTES`).
+    pub max_lockout: Duration,
+    /// Failed logins from one IP before each further attempt is delayed
+    /// (`LOGIN_IP_MAX_FAILED_ATTEMPTS`).
+    pub ip_max_attempts: u32,
+}
+
+impl LockoutPolicy {
+    /// How long to lock an account that has just reached `failed_attempts`, if at all.
+    /// Every `max_attempts` failures lock it again for twice as long as before.
+    pub fn account_lock_duration(&self, failed_attempts: i32) -> Option<Duration> {
+        if failed_attempts < self.max_attempts || failed_attempts % self.max_attempts != 0 {
+            return None;
+        }
+
+        let lockouts = (failed_attempts / self.max_attempts - 1).min(16) as u32;
+        Some((self.lockout * 2i32.pow(lockouts)).min(self.max_lockout))
+    }
+
+    /// Delay before the next attempt from an IP with `failures` recent failures.
+    fn ip_delay(&self, failures: u32) -> Option<Duration> {
+        if failures < self.ip_max_attempts {
+            return None;
+        }
+
+        let steps = (failures - self.ip_max_attempts).min(20);
+        Some(Duration::seconds(2i64.pow(steps)).min(self.max_lockout))
+    }
+}
+
+struct IpFailures {
+    count: u32,
+    last_failure: DateTime<Utc>,
+    blocked_until: Option<DateTime<Utc>>,
+}
+
+/// Tracks failed logins per client IP in memory and applies a progressive delay.
+/// Shared by all workers through ntex `State`.
+///
+/// At most `MAX_TRACKED_IPS` IPs are kept. A new IP evicts the one whose last
+/// failure is the oldest, so memory stays bounded without scanning every entry.
+pub struct LoginThrottle {
+    policy: LockoutPolicy,
+    ip_failures: Mutex<LruCache<String, IpFailures>>,
+}
+
+pub type SharedLoginThrottle = Arc<LoginThrottle>;
+
+impl LoginThrottle {
+    pub fn new(policy: LockoutPolicy) -> Self {
+        Self::with_capacity(
+            policy,
+            NonZeroUsize::new(MAX_TRACKED_IPS).expect("MAX_TRACKED_IPS is not zero"),
+        )
+    }
+
+    fn with_capacity(policy: LockoutPolicy, capacity: NonZeroUsize) -> Self {
+        Self {
+            policy,
+            ip_failures: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    pub fn policy(&self) -> &LockoutPolicy {
+        &self.policy
+    }
+
+    /// Seconds the IP still has to wait before it may try again, if it is delayed.
+    pub fn ip_retry_after(&self, ip: &str) -> Option<i64> {
+        let failures = self.ip_failures.lock().unwrap_or_else(|e| e.into_inner());
+        let blocked_until = failures.peek(ip)?.blocked_until?;
+        let remaining = (blocked_until - Utc::now()).num_seconds();
+        (remaining > 0).then_some(remaining)
+    }
+
+    /// Record a failed login from the IP. Failures older than the lockout period are forgotten.
+    pub fn record_ip_failure(&self, ip: &str) {
+        let now = Utc::now();
+        let mut failures = self.ip_failures.lock().unwrap_or_else(|e| e.into_inner());
+
+        let entry = failures.get_or_insert_mut(ip.to_string(), || IpFailures {
+            count: 0,
+            last_failure: now,
+            blocked_until: None,
+        });
+
+        if now - entry.last_failure >= self.policy.lockout {
+            entry.count = 0;
+        }
+
+        entry.count += 1;
+        entry.last_failure = now;
+        entry.blocked_until = self.policy.ip_delay(entry.count).map(|delay| now + delay);
+    }
+}
+
+/// Count a failed login on the account and lock it once the limit is reached.
+/// Returns the lock expiry when this attempt locked the account.
+pub async fn register_failed_login(
+    db: &DatabaseConnection,
+    policy: &LockoutPolicy,
+    user: &users::Model,
+    ip: &str,
+) -> Result<Option<DateTime<Utc>>, sea_orm::DbErr> {
+    let txn = db.begin().await?;
+
+    UsersEntity::update_many()
+        .col_expr(
+            users::Column::FailedLoginAttempts,
+            Expr::col(users::Column::FailedLoginAttempts).add(1),
+        )
+        .filter(users::Column::Id.eq(user.id))
+        .exec(&txn)
+        .await?;
+
+    let failed_attempts = UsersEntity::find_by_id(user.id)
+        .one(&txn)
+        .await?
+        .map(|user| user.failed_login_attempts)
+        .unwrap_or(user.failed_login_attempts + 1);
+
+    let locked_until = match policy.account_lock_duration(failed_attempts) {
+        Some(duration) => Utc::now() + duration,
+        None => {
+            txn.commit().await?;
+            return Ok(None);
+        }
+    };
+
+    UsersEntity::update_many()
+        .col_expr(users::Column::LockedUntil, Expr::value(locked_until))
+        .filter(users::Column::Id.eq(user.id))
+        .exec(&txn)
+        .await?;
+
+    // Insert audit log into activities table, so support can see why a user was blocked
+    let activity = ActivitiesActiveModel {
+        user_id: Set(user.id),
+        data_id: Set(user.id),
+        data_type: Set("user".to_string()),
+        activity_type: Set(Some("account_locked".to_string())),
+        activity_description: Set(Some(
+            "Account locked after repeated failed logins".to_string(),
+        )),
+        metadata: Set(Some(json!({
+            "ip": ip,
+            "failed_attempts": failed_attempts,
+            "locked_until": locked_until,
+        }))),
+        ..Default::default()
+    };
+
+    activity.insert(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(Some(locked_until))
+}
+
+/// Clear the failed login counter after a successful login.
+pub async fn reset_failed_logins(
+    db: &DatabaseConnection,
+    user: &users::Model,
+) -> Result<(), sea_orm::DbErr> {
+    if user.failed_login_attempts == 0 && user.locked_until.is_none() {
+        return Ok(());
+    }
+
+    UsersEntity::update_many()
+        .col_expr(users::Column::FailedLoginAttempts, Expr::value(0))
+        .col_expr(
+            users::Column::LockedUntil,
+            Expr::value(Option::<DateTime<Utc>>::None),
+        )
+        .filter(users::Column::Id.eq(user.id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LockoutPolicy {
+        LockoutPolicy {
+            max_attempts: 5,
+            lockout: Duration::minutes(15),
+            max_lockout: Duration::minutes(60),
+            ip_max_attempts: 3,
+        }
+    }
+
+    #[test]
+    fn no_lock_below_max_attempts() {
+        let policy = policy();
+        for failed in 0..5 {
+            assert_eq!(policy.account_lock_duration(failed), None);
+        }
+    }
+
+    #[test]
+    fn locks_at_max_attempts() {
+        assert_eq!(
+            policy().account_lock_duration(5),
+            Some(Duration::minutes(15))
+        );
+    }
+
+    #[test]
+    fn only_locks_again_at_multiples_of_max_attempts() {
+        let policy = policy();
+        for failed in 6..10 {
+            assert_eq!(policy.account_lock_duration(failed), None);
+        }
+    }
+
+    #[test]
+    fn lock_doubles_with_every_lockout() {
+        assert_eq!(
+            policy().account_lock_duration(10),
+            Some(Duration::minutes(30))
+        );
+    }
+
+    #[test]
+    fn lock_is_capped_at_max_lockout() {
+        let policy = policy();
+        assert_eq!(
+            policy.account_lock_duration(15),
+            Some(Duration::minutes(60))
+        );
+        assert_eq!(
+            policy.account_lock_duration(5 * 1000),
+            Some(Duration::minutes(60))
+        );
+    }
+
+    #[test]
+    fn ip_is_delayed_after_ip_max_attempts() {
+        let throttle = LoginThrottle::new(policy());
+
+        throttle.record_ip_failure("10.0.0.1");
+        throttle.record_ip_failure("10.0.0.1");
+        assert_eq!(throttle.ip_retry_after("10.0.0.1"), None);
+
+        throttle.record_ip_failure("10.0.0.1");
+        throttle.record_ip_failure("10.0.0.1");
+        assert!(throttle.ip_retry_after("10.0.0.1").is_some());
+        assert_eq!(throttle.ip_retry_after("10.0.0.2"), None);
+    }
+
+    #[test]
+    fn evicts_least_recently_failed_ip() {
+        let policy = LockoutPolicy {
+            ip_max_attempts: 1,
+            ..policy()
+        };
+        let throttle = LoginThrottle::with_capacity(policy, NonZeroUsize::new(2).unwrap());
+
+        throttle.record_ip_failure("10.0.0.1");
+        throttle.record_ip_failure("10.0.0.2");
+        throttle.record_ip_failure("10.0.0.1");
+        // The second failure raises the delay to two seconds, so the check below
+        // does not race the first one-second delay running out
+        throttle.record_ip_failure("10.0.0.3");
+        throttle.record_ip_failure("10.0.0.3");
+
+        assert!(throttle.ip_retry_after("10.0.0.1").is_some());
+        assert_eq!(throttle.ip_retry_after("10.0.0.2"), None);
+        assert!(throttle.ip_retry_after("10.0.0.3").is_some());
+    }
+}
@@ -0,0 +1,252 @@
+use crate::modules::database::entity::mfa_recovery_codes::{
+    self, ActiveModel as MfaRecoveryCodesActiveModel, Entity as MfaRecoveryCodesEntity,
+};
+use crate::modules::database::entity::users::{self, Entity as UsersEntity};
+use crate::modules::utils::security::hash_token;
+use chrono::Utc;
+use rand::Rng;
+use sea_orm::sea_query::{Condition, Expr};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, Set};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+/// Number of recovery codes handed out when two-factor authentication is enabled.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Seconds each TOTP code is valid for.
+const TOTP_STEP: u64 = 30;
+
+/// Characters of recovery codes, without the easily confused `0`, `o`, `1`, `l` and `i`.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// How a second factor was proven.
+pub enum SecondFactor {
+    Totp,
+    RecoveryCode,
+}
+
+/// Generate a new base32 encoded TOTP secret (160 bits, as RFC 4226 recommends).
+pub fn generate_totp_secret() -> String {
+    let bytes: [u8; 20] = rand::thread_rng().r#gen();
+    Secret::Raw(bytes.to_vec()).to_encoded().to_string()
+}
+
+fn totp(secret: &str, issuer: Option<String>, account: &str) -> Result<TOTP, String> {
+    let bytes = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| e.to_string())?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        TOTP_STEP,
+        bytes,
+        issuer,
+        account.to_string(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// `otpauth://` URI to enroll the secret in an authenticator app, usually shown as a QR code.
+pub fn otpauth_uri(secret: &str, issuer: &str, account: &str) -> Result<String, String> {
+    Ok(totp(secret, Some(issuer.to_string()), account)?.get_url())
+}
+
+/// Lower-case a code and drop the spaces and dashes users tend to type.
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Check a TOTP code against the previous, current and next time step, and return
+/// the matching step. Steps up to `last_used_step` are refused, so a code can
+/// only be used once.
+pub fn verify_totp(secret: &str, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    let totp = totp(secret, None, "").ok()?;
+    let code = normalize_code(code);
+    let now = Utc::now().timestamp() as u64 / TOTP_STEP;
+
+    (now.saturating_sub(1)..=now + 1)
+        .filter(|step| last_used_step.is_none_or(|last| *step as i64 > last))
+        .find(|step| totp.check(&code, step * TOTP_STEP))
+        .map(|step| step as i64)
+}
+
+/// Generate a fresh set of recovery codes, formatted like `abcde-fghjk`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..10)
+                .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())])
+                .map(char::from)
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Replace the user's recovery codes with hashes of `codes`.
+pub async fn replace_recovery_codes<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    codes: &[String],
+) -> Result<(), sea_orm::DbErr> {
+    MfaRecoveryCodesEntity::delete_many()
+        .filter(mfa_recovery_codes::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    for code in codes {
+        MfaRecoveryCodesActiveModel {
+            user_id: Set(user_id),
+            code_hash: Set(hash_token(&normalize_code(code))),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Number of recovery codes the user has not used yet.
+pub async fn remaining_recovery_codes<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<u64, sea_orm::DbErr> {
+    use sea_orm::PaginatorTrait;
+
+    MfaRecoveryCodesEntity::find()
+        .filter(mfa_recovery_codes::Column::UserId.eq(user_id))
+        .filter(mfa_recovery_codes::Column::UsedAt.is_null())
+        .count(db)
+        .await
+}
+
+/// Verify a TOTP or recovery code for a user with two-factor authentication
+/// enabled, and mark it as used. Returns `None` when the code is not valid.
+pub async fn verify_second_factor<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    code: &str,
+) -> Result<Option<SecondFactor>, sea_orm::DbErr> {
+    let secret = match (&user.totp_secret, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret,
+        _ => return Ok(None),
+    };
+
+    if let Some(step) = verify_totp(secret, code, user.totp_last_used_step) {
+        // Only one concurrent request may use the code
+        let result = UsersEntity::update_many()
+            .col_expr(users::Column::TotpLastUsedStep, Expr::value(step))
+            .filter(users::Column::Id.eq(user.id))
+            .filter(
+                Condition::any()
+                    .add(users::Column::TotpLastUsedStep.is_null())
+                    .add(users::Column::TotpLastUsedStep.lt(step)),
+            )
+            .exec(db)
+            .await?;
+
+        return Ok((result.rows_affected == 1).then_some(SecondFactor::Totp));
+    }
+
+    let result = MfaRecoveryCodesEntity::update_many()
+        .col_expr(mfa_recovery_codes::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(mfa_recovery_codes::Column::UserId.eq(user.id))
+        .filter(mfa_recovery_codes::Column::CodeHash.eq(hash_token(&normalize_code(code))))
+        .filter(mfa_recovery_codes::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok((result.rows_affected > 0).then_some(SecondFactor::RecoveryCode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+    fn current_step() -> i64 {
+        Utc::now().timestamp() / TOTP_STEP as i64
+    }
+
+    /// Code for the time step `offset` steps away from the current one.
+    fn code_at(offset: i64) -> (String, i64) {
+        let step = current_step() + offset;
+        let code = totp(SECRET, None, "")
+            .unwrap()
+            .generate(step as u64 * TOTP_STEP);
+        (code, step)
+    }
+
+    #[test]
+    fn accepts_codes_one_step_around_now() {
+        for offset in -1..=1 {
+            loop {
+                let before = current_step();
+                let (code, step) = code_at(offset);
+                let verified = verify_totp(SECRET, &code, None);
+                // Try again if the step ticked over in between
+                if current_step() == before {
+                    assert_eq!(verified, Some(step), "offset {}", offset);
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_codes_further_away() {
+        let (code, _) = code_at(-3);
+        assert_eq!(verify_totp(SECRET, &code, None), None);
+        let (code, _) = code_at(3);
+        assert_eq!(verify_totp(SECRET, &code, None), None);
+    }
+
+    #[test]
+    fn rejects_used_steps() {
+        let (code, _) = code_at(0);
+        let step = verify_totp(SECRET, &code, None).unwrap();
+
+        assert_eq!(verify_totp(SECRET, &code, Some(step)), None);
+        assert_eq!(verify_totp(SECRET, &code, Some(step + 1)), None);
+        assert_eq!(verify_totp(SECRET, &code, Some(step - 1)), Some(step));
+    }
+
+    #[test]
+    fn accepts_codes_with_spaces_and_dashes() {
+        let (code, _) = code_at(0);
+        let spaced = format!("{} {}", &code[..3], &code[3..]);
+        assert!(verify_totp(SECRET, &spaced, None).is_some());
+    }
+
+    #[test]
+    fn rejects_wrong_codes() {
+        assert_eq!(verify_totp(SECRET, "abcdef", None), None);
+        assert_eq!(verify_totp(SECRET, "", None), None);
+        assert_eq!(verify_totp("not base32!", "123456", None), None);
+    }
+
+    #[test]
+    fn normalizes_codes() {
+        assert_eq!(normalize_code(" ABCDE-fghjk "), "abcdefghjk");
+        assert_eq!(normalize_code("123 456"), "123456");
+        assert_eq!(normalize_code("a-b\tc"), "abc");
+    }
+
+    #[test]
+    fn recovery_codes_survive_normalization() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in codes {
+            assert_eq!(code.len(), 11);
+            assert_eq!(normalize_code(&code.to_uppercase()), code.replace('-', ""));
+        }
+    }
+}
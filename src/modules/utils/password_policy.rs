@@ -0,0 +1,300 @@
+use sha1::{Digest, Sha1};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use validator::{ValidationError, ValidationErrors};
+
+/// Passwords longer than this are rejected, hashing them would only waste CPU.
+pub const MAX_LENGTH: usize = 256;
+
+/// Personal values shorter than this are not checked, they match too many passwords.
+const MIN_PERSONAL_VALUE_LENGTH: usize = 3;
+
+/// Rules every new password must follow, shared by registration, change-password
+/// and password reset.
+///
+/// Configured with `PASSWORD_MIN_LENGTH`, `PASSWORD_MIN_CHARACTER_CLASSES` (out of
+/// lowercase, uppercase, digits and symbols) and `PASSWORD_BLOCKLIST_FILE`, and
+/// built once at startup as part of `AppConfig`.
+pub struct PasswordPolicy {
+    min_length: usize,
+    min_character_classes: usize,
+    /// Upper-case SHA-1 hex digests of blocked passwords.
+    blocklist: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    pub fn new(
+        min_length: usize,
+        min_character_classes: usize,
+        blocklist: HashSet<String>,
+    ) -> Self {
+        Self {
+            min_length,
+            min_character_classes,
+            blocklist,
+        }
+    }
+
+    /// Check a password, `personal` holding the email and names of the account.
+    pub fn check(&self, password: &str, personal: &[&str]) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            let mut error = ValidationError::new("password_too_short").with_message(Cow::from(
+                format!("password must be at least {} characters", self.min_length),
+            ));
+            error.add_param(Cow::from("min"), &self.min_length);
+            errors.push(error);
+        }
+
+        if length > MAX_LENGTH {
+            let mut error = ValidationError::new("password_too_long").with_message(Cow::from(
+                format!("password must be at most {} characters", MAX_LENGTH),
+            ));
+            error.add_param(Cow::from("max"), &MAX_LENGTH);
+            errors.push(error);
+        }
+
+        let classes = character_classes(password);
+        if classes < self.min_character_classes {
+            let mut error = ValidationError::new("password_too_simple").with_message(Cow::from(
+                format!(
+                    "password must contain at least {} of: lowercase letters, uppercase letters, digits, symbols",
+                    self.min_character_classes
+                ),
+            ));
+            error.add_param(Cow::from("min_classes"), &self.min_character_classes);
+            errors.push(error);
+        }
+
+        let lowered = password.to_lowercase();
+        if personal_values(personal).any(|value| lowered.contains(&value)) {
+            errors.push(
+                ValidationError::new("password_contains_personal_info")
+                    .with_message(Cow::from("password must not contain your email or name")),
+            );
+        }
+
+        if self.is_blocked(password) {
+            errors.push(
+                ValidationError::new("password_breached").with_message(Cow::from(
+                    "password is too common or has appeared in a data breach",
+                )),
+            );
+        }
+
+        errors
+    }
+
+    fn is_blocked(&self, password: &str) -> bool {
+        !self.blocklist.is_empty()
+            && (self.blocklist.contains(&sha1_hex(password))
+                || self.blocklist.contains(&sha1_hex(&password.to_lowercase())))
+    }
+}
+
+fn sha1_hex(value: &str) -> String {
+    hex::encode_upper(Sha1::digest(value.as_bytes()))
+}
+
+/// Load blocked passwords from a file with one entry per line. An entry is either
+/// a plain password or a SHA-1 hash (optionally followed by `:count`), so a
+/// downloaded breached-password hash list can be used as is.
+pub fn load_blocklist(path: &str) -> Result<HashSet<String>, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+
+    let blocklist = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let hash = line.split(':').next().unwrap_or(line);
+            if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                hash.to_uppercase()
+            } else {
+                sha1_hex(&line.to_lowercase())
+            }
+        })
+        .collect();
+
+    Ok(blocklist)
+}
+
+/// Number of character classes (lowercase, uppercase, digits, symbols) used.
+fn character_classes(password: &str) -> usize {
+    let checks: [fn(&char) -> bool; 4] = [
+        char::is_ascii_lowercase,
+        char::is_ascii_uppercase,
+        char::is_ascii_digit,
+        |c: &char| !c.is_ascii_alphanumeric(),
+    ];
+
+    checks
+        .iter()
+        .filter(|check| password.chars().any(|c| check(&c)))
+        .count()
+}
+
+/// Lower-cased personal values worth checking: each name, and the local part of an email.
+fn personal_values<'a>(personal: &'a [&'a str]) -> impl Iterator<Item = String> + 'a {
+    personal
+        .iter()
+        .map(|value| {
+            value
+                .split('@')
+                .next()
+                .unwrap_or(value)
+                .trim()
+                .to_lowercase()
+        })
+        .filter(|value| value.chars().count() >= MIN_PERSONAL_VALUE_LENGTH)
+}
+
+/// Check a new password against the password policy. Violations are returned as
+/// validator errors on `field`, ready for the 422 `validation_error` response.
+pub fn check_password_policy(
+    policy: &PasswordPolicy,
+    field: &'static str,
+    password: &str,
+    personal: &[&str],
+) -> Result<(), ValidationErrors> {
+    let violations = policy.check(password, personal);
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    let mut errors = ValidationErrors::new();
+    for violation in violations {
+        errors.add(field, violation);
+    }
+    Err(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(min_length: usize, min_character_classes: usize) -> PasswordPolicy {
+        PasswordPolicy::new(min_length, min_character_classes, HashSet::new())
+    }
+
+    fn codes(errors: Vec<ValidationError>) -> Vec<String> {
+        errors
+            .into_iter()
+            .map(|error| error.code.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_password_following_the_rules() {
+        assert!(policy(8, 3).check("Correct-horse-9", &[]).is_empty());
+    }
+
+    #[test]
+    fn rejects_too_short_and_too_long() {
+        let policy = policy(8, 1);
+
+        assert_eq!(codes(policy.check("short", &[])), ["password_too_short"]);
+        assert!(policy.check("eight ch", &[]).is_empty());
+        assert!(policy.check(&"a".repeat(MAX_LENGTH), &[]).is_empty());
+        assert_eq!(
+            codes(policy.check(&"a".repeat(MAX_LENGTH + 1), &[])),
+            ["password_too_long"]
+        );
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        let policy = policy(8, 1);
+        assert_eq!(codes(policy.check("ééééééé", &[])), ["password_too_short"]);
+        assert!(policy.check("éééééééé", &[]).is_empty());
+    }
+
+    #[test]
+    fn counts_character_classes() {
+        assert_eq!(character_classes("lowercase"), 1);
+        assert_eq!(character_classes("Mixedcase"), 2);
+        assert_eq!(character_classes("Mixed4case"), 3);
+        assert_eq!(character_classes("Mixed4case!"), 4);
+        assert_eq!(character_classes("ünïcödé"), 2);
+
+        let policy = policy(8, 3);
+        assert_eq!(
+            codes(policy.check("Mixedcase", &[])),
+            ["password_too_simple"]
+        );
+        assert!(policy.check("Mixed4case", &[]).is_empty());
+    }
+
+    #[test]
+    fn rejects_email_local_part_and_names() {
+        let policy = policy(8, 1);
+        let personal = ["Jane.Doe@example.com", "Jane", "Doe"];
+
+        assert_eq!(
+            codes(policy.check("my-jane.doe-password", &personal)),
+            ["password_contains_personal_info"]
+        );
+        assert_eq!(
+            codes(policy.check("i-am-JANE-really", &personal)),
+            ["password_contains_personal_info"]
+        );
+        // The domain is not personal
+        assert!(policy.check("example.com-rocks", &personal).is_empty());
+    }
+
+    #[test]
+    fn ignores_short_personal_values() {
+        let policy = policy(8, 1);
+        assert!(
+            policy
+                .check("an-xi-password", &["Xi", "an@example.com"])
+                .is_empty()
+        );
+        assert_eq!(
+            codes(policy.check("bob-password", &["Bob"])),
+            ["password_contains_personal_info"]
+        );
+    }
+
+    #[test]
+    fn rejects_blocklisted_passwords() {
+        let path = std::env::temp_dir().join(format!("blocklist-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            format!(
+                "# comment\n\nSummer2024!\n{}:52579\n",
+                sha1_hex("Password123").to_lowercase()
+            ),
+        )
+        .unwrap();
+        let blocklist = load_blocklist(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(blocklist.len(), 2);
+        let policy = PasswordPolicy::new(8, 1, blocklist);
+
+        // Plain entries match regardless of case
+        assert_eq!(
+            codes(policy.check("summer2024!", &[])),
+            ["password_breached"]
+        );
+        assert_eq!(
+            codes(policy.check("SUMMER2024!", &[])),
+            ["password_breached"]
+        );
+        // Hash entries match the exact password or its lower-cased form
+        assert_eq!(
+            codes(policy.check("Password123", &[])),
+            ["password_breached"]
+        );
+        assert!(policy.check("Password1234", &[]).is_empty());
+    }
+
+    #[test]
+    fn missing_blocklist_is_an_error() {
+        assert!(load_blocklist("/nonexistent/blocklist.txt").is_err());
+    }
+}
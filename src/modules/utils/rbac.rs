@@ -0,0 +1,46 @@
+use crate::modules::database::entity::permissions::{self, Entity as PermissionsEntity};
+use crate::modules::database::entity::role_permissions;
+use crate::modules::database::entity::roles::{self, Entity as RolesEntity};
+use crate::modules::database::entity::user_roles;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QuerySelect,
+    RelationTrait,
+};
+
+/// Names of the roles granted to a user, as embedded in access tokens.
+pub async fn user_role_names<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<Vec<String>, sea_orm::DbErr> {
+    let roles = RolesEntity::find()
+        .join(JoinType::InnerJoin, roles::Relation::UserRoles.def())
+        .filter(user_roles::Column::UserId.eq(user_id))
+        .all(db)
+        .await?;
+
+    Ok(roles.into_iter().map(|role| role.name).collect())
+}
+
+/// Whether any of the named roles grants `permission`.
+pub async fn has_permission<C: ConnectionTrait>(
+    db: &C,
+    roles: &[String],
+    permission: &str,
+) -> Result<bool, sea_orm::DbErr> {
+    if roles.is_empty() {
+        return Ok(false);
+    }
+
+    let matches = PermissionsEntity::find()
+        .join(
+            JoinType::InnerJoin,
+            permissions::Relation::RolePermissions.def(),
+        )
+        .join(JoinType::InnerJoin, role_permissions::Relation::Roles.def())
+        .filter(permissions::Column::Name.eq(permission))
+        .filter(roles::Column::Name.is_in(roles.iter().cloned()))
+        .count(db)
+        .await?;
+
+    Ok(matches > 0)
+}
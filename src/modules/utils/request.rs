@@ -0,0 +1,120 @@
+use ntex::http::HeaderMap;
+use std::net::SocketAddr;
+
+/// Header carrying the ID that correlates a request with its log lines.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Best-effort IP address of the client that sent the request.
+///
+/// `X-Forwarded-For` is only looked at when `trusted_proxies` is not zero. Every
+/// proxy appends the address it received the request from, so the client is the
+/// entry `trusted_proxies` hops from the right; entries left of it were sent by
+/// the client and could be anything.
+pub fn client_ip(
+    peer_addr: Option<SocketAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: usize,
+) -> String {
+    if trusted_proxies > 0 {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                let entries: Vec<&str> = value.split(',').map(str::trim).collect();
+                // Fewer entries than proxies: the request skipped the outer ones
+                entries
+                    .get(entries.len().saturating_sub(trusted_proxies))
+                    .copied()
+            })
+            .filter(|ip| !ip.is_empty())
+            .map(str::to_string);
+
+        if let Some(ip) = forwarded {
+            return ip;
+        }
+    }
+
+    peer_addr
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Use the client's `X-Request-Id` when it looks sane, otherwise generate one.
+pub fn request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= 128
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Run `future` with `id` as the current request ID.
+pub async fn with_request_id<F: Future>(id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(id, future).await
+}
+
+/// ID of the request being handled, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntex::http::header::{HeaderName, HeaderValue};
+
+    fn peer() -> Option<SocketAddr> {
+        Some("10.0.0.9:51234".parse().unwrap())
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-forwarded-for"),
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn ignores_forwarded_for_without_trusted_proxies() {
+        let headers = forwarded_for("203.0.113.7");
+        assert_eq!(client_ip(peer(), &headers, 0), "10.0.0.9");
+    }
+
+    #[test]
+    fn takes_the_entry_appended_by_the_proxy() {
+        // The client sent a made-up first entry, the proxy appended the real one
+        let headers = forwarded_for("1.2.3.4, 203.0.113.7");
+        assert_eq!(client_ip(peer(), &headers, 1), "203.0.113.7");
+    }
+
+    #[test]
+    fn counts_hops_from_the_right() {
+        let headers = forwarded_for("1.2.3.4, 203.0.113.7, 198.51.100.2");
+        assert_eq!(client_ip(peer(), &headers, 2), "203.0.113.7");
+        // Fewer entries than proxies, the innermost proxy's entry is the client
+        assert_eq!(client_ip(peer(), &headers, 5), "1.2.3.4");
+    }
+
+    #[test]
+    fn falls_back_to_the_peer_address() {
+        assert_eq!(client_ip(peer(), &HeaderMap::new(), 1), "10.0.0.9");
+        assert_eq!(
+            client_ip(peer(), &forwarded_for("1.2.3.4, "), 1),
+            "10.0.0.9"
+        );
+        assert_eq!(client_ip(None, &HeaderMap::new(), 1), "unknown");
+    }
+}
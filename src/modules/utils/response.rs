@@ -1,3 +1,4 @@
+use crate::modules::utils::request::current_request_id;
 use ntex::web::HttpResponse;
 use serde::Serialize;
 
@@ -35,6 +36,10 @@ where
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub details: Option<T>,
+
+    /// `X-Request-Id` of the request, for clients to quote in support tickets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl<T> ErrorResponse<T>
@@ -47,6 +52,7 @@ where
             success: false,
             message: message.into(),
             details,
+            request_id: current_request_id(),
         }
     }
 }
@@ -64,3 +70,12 @@ pub fn send_error<T: Serialize>(
     HttpResponse::build(ntex::http::StatusCode::from_u16(status).unwrap())
         .json(&ErrorResponse::new(code, message, details))
 }
+
+/// Add a `Retry-After` header (in seconds) to a response.
+pub fn with_retry_after(mut response: HttpResponse, seconds: i64) -> HttpResponse {
+    response.headers_mut().insert(
+        ntex::http::header::RETRY_AFTER,
+        ntex::http::header::HeaderValue::from(seconds.max(1)),
+    );
+    response
+}
@@ -1,17 +1,259 @@
-use bcrypt::{DEFAULT_COST, hash};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use ntex::web;
+use ntex::web::error::BlockingError;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-/// Hash a plaintext password using bcrypt.
+/// Algorithm of a stored password hash, detected from its prefix.
+#[derive(PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Bcrypt,
+    Argon2id,
+    Unknown,
+}
+
+impl HashAlgorithm {
+    pub fn detect(hashed: &str) -> Self {
+        if hashed.starts_with("$argon2id$") {
+            HashAlgorithm::Argon2id
+        } else if ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| hashed.starts_with(prefix))
+        {
+            HashAlgorithm::Bcrypt
+        } else {
+            HashAlgorithm::Unknown
+        }
+    }
+}
+
+/// Hashes new passwords with Argon2id and verifies both Argon2id and legacy bcrypt hashes.
 ///
-/// # Panics
-/// Will panic if hashing fails (e.g., low system entropy).
-pub fn hash_password(plain: &str) -> String {
-    hash(plain, DEFAULT_COST).expect("Failed to hash password")
-}
-
-pub fn verify_password(plain: &str, hashed: &str) -> bool {
-    // Example using argon2 or bcrypt (choose based on your hash_password implementation)
-    // If you use bcrypt:
-    bcrypt::verify(plain, hashed).unwrap_or(false)
-    // If you use argon2:
-    // argon2::verify_encoded(hashed, plain.as_bytes()).unwrap_or(false)
+/// Argon2id cost parameters come from `ARGON2_MEMORY_KIB`, `ARGON2_ITERATIONS` and
+/// `ARGON2_PARALLELISM` (OWASP recommended minimums by default). Part of `AppConfig`.
+#[derive(Clone)]
+pub struct PasswordHasher {
+    params: Params,
+    /// Upper bound for pending password jobs (`PASSWORD_HASH_MAX_PENDING`).
+    max_pending: usize,
+}
+
+impl PasswordHasher {
+    pub fn new(params: Params, max_pending: usize) -> Self {
+        Self {
+            params,
+            max_pending,
+        }
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    /// Hash a plaintext password using Argon2id.
+    pub fn hash(&self, plain: &str) -> Result<String, String> {
+        use argon2::PasswordHasher as _;
+
+        let salt = SaltString::generate(&mut OsRng);
+        self.argon2()
+            .hash_password(plain.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|_| "Failed to hash password".to_string())
+    }
+
+    /// Verify a plaintext password against a stored bcrypt or Argon2id hash.
+    pub fn verify(&self, plain: &str, hashed: &str) -> bool {
+        use argon2::PasswordVerifier as _;
+
+        match HashAlgorithm::detect(hashed) {
+            HashAlgorithm::Bcrypt => bcrypt::verify(plain, hashed).unwrap_or(false),
+            HashAlgorithm::Argon2id => PasswordHash::new(hashed)
+                .map(|hash| {
+                    self.argon2()
+                        .verify_password(plain.as_bytes(), &hash)
+                        .is_ok()
+                })
+                .unwrap_or(false),
+            HashAlgorithm::Unknown => false,
+        }
+    }
+
+    /// Whether a stored hash uses an outdated algorithm or weaker parameters
+    /// than configured, and should be replaced on the next successful login.
+    pub fn needs_rehash(&self, hashed: &str) -> bool {
+        if HashAlgorithm::detect(hashed) != HashAlgorithm::Argon2id {
+            return true;
+        }
+
+        let params = match PasswordHash::new(hashed).and_then(|hash| Params::try_from(&hash)) {
+            Ok(params) => params,
+            Err(_) => return true,
+        };
+
+        params.m_cost() < self.params.m_cost()
+            || params.t_cost() < self.params.t_cost()
+            || params.p_cost() < self.params.p_cost()
+    }
+}
+
+/// Why a password could not be hashed or verified.
+#[derive(Debug)]
+pub enum PasswordError {
+    /// Too many hashing jobs are already pending.
+    Busy,
+    /// Hashing failed or the blocking pool is gone.
+    Failed,
+}
+
+/// Number of password jobs running or waiting on the blocking pool.
+static PENDING_JOBS: AtomicUsize = AtomicUsize::new(0);
+
+/// Releases a pending job slot once the job has finished, even if the request
+/// waiting for it was dropped.
+struct PendingJob;
+
+impl PendingJob {
+    fn acquire(max: usize) -> Result<Self, PasswordError> {
+        PENDING_JOBS
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .map(|_| PendingJob)
+            .map_err(|_| PasswordError::Busy)
+    }
+}
+
+impl Drop for PendingJob {
+    fn drop(&mut self) {
+        PENDING_JOBS.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Run a CPU-heavy password job on the blocking pool instead of an ntex worker.
+async fn run_blocking<T, F>(hasher: &PasswordHasher, job: F) -> Result<T, PasswordError>
+where
+    F: FnOnce(PasswordHasher) -> Result<T, PasswordError> + Send + Sync + 'static,
+    T: Send + 'static,
+{
+    let slot = PendingJob::acquire(hasher.max_pending)?;
+    let hasher = hasher.clone();
+
+    web::block(move || {
+        let _slot = slot;
+        job(hasher)
+    })
+    .await
+    .map_err(|e| match e {
+        BlockingError::Error(e) => e,
+        BlockingError::Canceled => PasswordError::Failed,
+    })
+}
+
+/// Hash a plaintext password using Argon2id on the blocking pool.
+pub async fn hash_password(hasher: &PasswordHasher, plain: &str) -> Result<String, PasswordError> {
+    let plain = plain.to_string();
+    run_blocking(hasher, move |hasher| {
+        hasher.hash(&plain).map_err(|_| PasswordError::Failed)
+    })
+    .await
+}
+
+/// Verify a plaintext password against a stored bcrypt or Argon2id hash on the blocking pool.
+pub async fn verify_password(
+    hasher: &PasswordHasher,
+    plain: &str,
+    hashed: &str,
+) -> Result<bool, PasswordError> {
+    let plain = plain.to_string();
+    let hashed = hashed.to_string();
+    run_blocking(hasher, move |hasher| Ok(hasher.verify(&plain, &hashed))).await
+}
+
+/// Generate a random, URL-safe token for one-time links (password reset, email verification).
+pub fn generate_secure_token() -> String {
+    use rand::RngCore;
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hash a one-time token with SHA-256 so only the hash is stored in the database.
+pub fn hash_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters, so hashing in tests is fast.
+    fn hasher(memory_kib: u32, iterations: u32) -> PasswordHasher {
+        PasswordHasher::new(Params::new(memory_kib, iterations, 1, None).unwrap(), 4)
+    }
+
+    #[test]
+    fn detects_hash_algorithms() {
+        let argon2 = hasher(64, 1).hash("secret").unwrap();
+        assert!(HashAlgorithm::detect(&argon2) == HashAlgorithm::Argon2id);
+
+        for prefix in ["$2a$", "$2b$", "$2x$", "$2y$"] {
+            let bcrypt = format!("{}04$abcdefghijklmnopqrstuu", prefix);
+            assert!(HashAlgorithm::detect(&bcrypt) == HashAlgorithm::Bcrypt);
+        }
+
+        for hashed in ["$argon2i$v=19$m=64,t=1,p=1$c2FsdA$aGFzaA", "plain", ""] {
+            assert!(HashAlgorithm::detect(hashed) == HashAlgorithm::Unknown);
+        }
+    }
+
+    #[test]
+    fn verifies_argon2id_and_bcrypt_hashes() {
+        let hasher = hasher(64, 1);
+
+        let argon2 = hasher.hash("secret").unwrap();
+        assert!(hasher.verify("secret", &argon2));
+        assert!(!hasher.verify("Secret", &argon2));
+
+        let bcrypt = bcrypt::hash("secret", 4).unwrap();
+        assert!(hasher.verify("secret", &bcrypt));
+        assert!(!hasher.verify("Secret", &bcrypt));
+
+        assert!(!hasher.verify("secret", "secret"));
+    }
+
+    #[test]
+    fn rehashes_bcrypt_and_weaker_argon2id() {
+        let current = hasher(128, 2);
+
+        assert!(current.needs_rehash(&bcrypt::hash("secret", 4).unwrap()));
+        assert!(current.needs_rehash("not a hash"));
+        assert!(!current.needs_rehash(&current.hash("secret").unwrap()));
+
+        // Weaker memory or iterations than configured
+        assert!(current.needs_rehash(&hasher(64, 2).hash("secret").unwrap()));
+        assert!(current.needs_rehash(&hasher(128, 1).hash("secret").unwrap()));
+        // Stronger parameters are kept
+        assert!(!current.needs_rehash(&hasher(256, 3).hash("secret").unwrap()));
+    }
+
+    #[test]
+    fn hashes_tokens_with_sha256() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(hash_token("abc"), hash_token("abd"));
+    }
+
+    #[test]
+    fn generates_distinct_hex_tokens() {
+        let token = generate_secure_token();
+        assert_eq!(token.len(), 64);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, generate_secure_token());
+    }
 }
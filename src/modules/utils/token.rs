@@ -0,0 +1,312 @@
+use crate::modules::config::AppConfig;
+use crate::modules::metrics::metrics;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind as JwtErrorKind};
+use serde::{Deserialize, Serialize};
+
+/// Kind of token, so a refresh token can never be used as an access token and vice versa.
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    Access,
+    Refresh,
+    EmailVerification,
+    MfaChallenge,
+}
+
+impl TokenType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TokenType::Access => "access",
+            TokenType::Refresh => "refresh",
+            TokenType::EmailVerification => "email_verification",
+            TokenType::MfaChallenge => "mfa_challenge",
+        }
+    }
+}
+
+/// Allowed clock skew in seconds when checking `iat`.
+const IAT_LEEWAY_SECONDS: usize = 60;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Claims {
+    pub sub: i32,
+    pub user_id: i32,
+    pub email: String,
+    pub exp: usize,
+    pub iat: usize,
+    pub jti: String,
+    /// Session family the token belongs to (`user_sessions.family_id`).
+    pub sid: String,
+    pub typ: TokenType,
+    /// Roles of the user when the token was issued. Only set on access tokens.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+
+/// Claims of the signed link sent to confirm an email address.
+#[derive(Deserialize, Serialize)]
+pub struct EmailVerificationClaims {
+    pub sub: i32,
+    pub email: String,
+    pub exp: usize,
+    pub iat: usize,
+    pub typ: TokenType,
+}
+
+/// Claims of the challenge token returned by `login_user` when the account has
+/// two-factor authentication enabled. It only proves the password was correct.
+#[derive(Deserialize, Serialize)]
+pub struct MfaChallengeClaims {
+    pub sub: i32,
+    pub exp: usize,
+    pub iat: usize,
+    pub typ: TokenType,
+}
+
+/// Sign `claims` and count the issued token.
+fn issue<T: Serialize>(config: &AppConfig, typ: TokenType, claims: &T) -> Result<String, JwtError> {
+    let token = config.jwt_keys.encode(claims)?;
+    metrics().token_issued(typ);
+    Ok(token)
+}
+
+/// A freshly signed refresh token together with the values persisted in `user_sessions`.
+pub struct RefreshToken {
+    pub token: String,
+    pub jti: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub fn generate_access_token(
+    config: &AppConfig,
+    user_id: i32,
+    email: &str,
+    sid: &str,
+    roles: &[String],
+) -> Result<String, JwtError> {
+    // Generate expiration timestamp
+    let expiration = (Utc::now() + config.access_token_lifetime).timestamp() as usize;
+
+    let claims = Claims {
+        sub: user_id,
+        user_id,
+        email: email.to_string(),
+        exp: expiration,
+        iat: Utc::now().timestamp() as usize,
+        jti: uuid::Uuid::new_v4().to_string(),
+        sid: sid.to_string(),
+        typ: TokenType::Access,
+        roles: roles.to_vec(),
+    };
+
+    issue(config, TokenType::Access, &claims)
+}
+
+pub fn generate_refresh_token(
+    config: &AppConfig,
+    user_id: i32,
+    email: &str,
+    sid: &str,
+) -> Result<RefreshToken, JwtError> {
+    // Generate expiration timestamp
+    let expires_at = Utc::now() + config.refresh_token_lifetime;
+
+    let jti = uuid::Uuid::new_v4().to_string();
+
+    let claims = Claims {
+        sub: user_id,
+        user_id,
+        email: email.to_string(),
+        exp: expires_at.timestamp() as usize,
+        iat: Utc::now().timestamp() as usize,
+        jti: jti.clone(),
+        sid: sid.to_string(),
+        typ: TokenType::Refresh,
+        roles: Vec::new(),
+    };
+
+    let token = issue(config, TokenType::Refresh, &claims)?;
+
+    Ok(RefreshToken {
+        token,
+        jti,
+        expires_at,
+    })
+}
+
+/// Verify a token's signature, `exp` and `iat`, check that it is of the expected
+/// type and return its claims.
+pub fn decode_token(
+    config: &AppConfig,
+    token: &str,
+    expected: TokenType,
+) -> Result<Claims, JwtError> {
+    let claims = config.jwt_keys.decode::<Claims>(token)?;
+
+    // Reject tokens claiming to be issued in the future
+    if claims.iat > Utc::now().timestamp() as usize + IAT_LEEWAY_SECONDS {
+        return Err(JwtErrorKind::InvalidToken.into());
+    }
+
+    if claims.typ != expected {
+        return Err(JwtErrorKind::InvalidToken.into());
+    }
+
+    Ok(claims)
+}
+
+pub fn generate_email_verification_token(
+    config: &AppConfig,
+    user_id: i32,
+    email: &str,
+) -> Result<String, JwtError> {
+    // Generate expiration timestamp
+    let expiration = (Utc::now() + config.email_verification_lifetime).timestamp() as usize;
+
+    let claims = EmailVerificationClaims {
+        sub: user_id,
+        email: email.to_string(),
+        exp: expiration,
+        iat: Utc::now().timestamp() as usize,
+        typ: TokenType::EmailVerification,
+    };
+
+    issue(config, TokenType::EmailVerification, &claims)
+}
+
+/// Verify the signature and expiry of an email verification token and return its claims.
+pub fn decode_email_verification_token(
+    config: &AppConfig,
+    token: &str,
+) -> Result<EmailVerificationClaims, JwtError> {
+    let claims = config.jwt_keys.decode::<EmailVerificationClaims>(token)?;
+
+    if claims.typ != TokenType::EmailVerification {
+        return Err(JwtErrorKind::InvalidToken.into());
+    }
+
+    Ok(claims)
+}
+
+pub fn generate_mfa_challenge_token(config: &AppConfig, user_id: i32) -> Result<String, JwtError> {
+    // Generate expiration timestamp
+    let expiration = (Utc::now() + config.mfa_challenge_lifetime).timestamp() as usize;
+
+    let claims = MfaChallengeClaims {
+        sub: user_id,
+        exp: expiration,
+        iat: Utc::now().timestamp() as usize,
+        typ: TokenType::MfaChallenge,
+    };
+
+    issue(config, TokenType::MfaChallenge, &claims)
+}
+
+/// Verify the signature and expiry of an MFA challenge token and return its claims.
+pub fn decode_mfa_challenge_token(
+    config: &AppConfig,
+    token: &str,
+) -> Result<MfaChallengeClaims, JwtError> {
+    let claims = config.jwt_keys.decode::<MfaChallengeClaims>(token)?;
+
+    if claims.typ != TokenType::MfaChallenge {
+        return Err(JwtErrorKind::InvalidToken.into());
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::utils::jwt_keys::JwtKeys;
+    use std::sync::Arc;
+
+    fn access_token(config: &AppConfig) -> String {
+        generate_access_token(config, 7, "user@example.com", "family", &[]).unwrap()
+    }
+
+    #[test]
+    fn decodes_access_tokens() {
+        let config = AppConfig::for_tests();
+        let claims = decode_token(&config, &access_token(&config), TokenType::Access).unwrap();
+
+        assert_eq!(claims.user_id, 7);
+        assert_eq!(claims.sid, "family");
+        assert!(claims.typ == TokenType::Access);
+    }
+
+    #[test]
+    fn rejects_other_token_types_as_access_tokens() {
+        let config = AppConfig::for_tests();
+        let refresh = generate_refresh_token(&config, 7, "user@example.com", "family").unwrap();
+        let verification =
+            generate_email_verification_token(&config, 7, "user@example.com").unwrap();
+        let challenge = generate_mfa_challenge_token(&config, 7).unwrap();
+
+        for token in [&refresh.token, &verification, &challenge] {
+            assert!(decode_token(&config, token, TokenType::Access).is_err());
+        }
+        assert!(decode_token(&config, &refresh.token, TokenType::Refresh).is_ok());
+    }
+
+    #[test]
+    fn rejects_access_tokens_elsewhere() {
+        let config = AppConfig::for_tests();
+        let access = access_token(&config);
+
+        assert!(decode_token(&config, &access, TokenType::Refresh).is_err());
+        assert!(decode_email_verification_token(&config, &access).is_err());
+        assert!(decode_mfa_challenge_token(&config, &access).is_err());
+    }
+
+    #[test]
+    fn decodes_verification_and_challenge_tokens() {
+        let config = AppConfig::for_tests();
+
+        let verification =
+            generate_email_verification_token(&config, 7, "user@example.com").unwrap();
+        let claims = decode_email_verification_token(&config, &verification).unwrap();
+        assert_eq!((claims.sub, claims.email.as_str()), (7, "user@example.com"));
+        assert!(decode_mfa_challenge_token(&config, &verification).is_err());
+
+        let challenge = generate_mfa_challenge_token(&config, 7).unwrap();
+        assert_eq!(
+            decode_mfa_challenge_token(&config, &challenge).unwrap().sub,
+            7
+        );
+        assert!(decode_email_verification_token(&config, &challenge).is_err());
+    }
+
+    #[test]
+    fn rejects_tokens_issued_in_the_future() {
+        let config = AppConfig::for_tests();
+        let now = Utc::now().timestamp() as usize;
+        let claims = Claims {
+            sub: 7,
+            user_id: 7,
+            email: "user@example.com".to_string(),
+            exp: now + 3600,
+            iat: now + 600,
+            jti: "jti".to_string(),
+            sid: "family".to_string(),
+            typ: TokenType::Access,
+            roles: Vec::new(),
+        };
+        let token = config.jwt_keys.encode(&claims).unwrap();
+
+        assert!(decode_token(&config, &token, TokenType::Access).is_err());
+    }
+
+    #[test]
+    fn rejects_tokens_signed_with_another_secret() {
+        let config = AppConfig::for_tests();
+        let other = AppConfig {
+            jwt_keys: Arc::new(JwtKeys::hmac("another-secret-that-is-long-enough", None)),
+            ..AppConfig::for_tests()
+        };
+
+        assert!(decode_token(&config, &access_token(&other), TokenType::Access).is_err());
+    }
+}